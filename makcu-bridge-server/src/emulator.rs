@@ -3,11 +3,14 @@ use std::sync::{
     atomic::{AtomicBool, Ordering},
 };
 
-use tokio::sync::{mpsc, watch};
+use makcu::{ButtonState, MouseButton, watch};
+use tokio::sync::mpsc;
 
 use crate::Makcu;
 
 pub struct InputEmulator {
+    // 클라이언트가 요청한 잠금 상태. 버튼 보고마다 읽으므로 그때마다 장치에 왕복으로
+    // 묻지 않도록 여기에 둔다. 장치의 실제 상태는 `Makcu::is_locked` 로 읽는다
     is_locked: AtomicBool,
     is_pending: AtomicBool,
     pending_tx: mpsc::Sender<()>,
@@ -47,8 +50,8 @@ impl InputEmulator {
     pub async fn lock(&self) -> anyhow::Result<()> {
         let was_locked = self.is_locked.swap(true, Ordering::AcqRel);
         if !was_locked {
-            self.makcu.lock_ml().await?;
-            self.makcu.lock_ms1().await?;
+            self.makcu.lock(MouseButton::Left).await?;
+            self.makcu.lock(MouseButton::Side1).await?;
         }
         Ok(())
     }
@@ -56,9 +59,13 @@ impl InputEmulator {
     pub async fn unlock(&self) -> anyhow::Result<()> {
         let was_locked = self.is_locked.swap(false, Ordering::AcqRel);
         if was_locked {
-            self.makcu.unlock_ml().await?;
-            self.makcu.unlock_ms1().await?;
-            self.release().await?;
+            // 하나가 실패해도 나머지 해제는 모두 보낸다
+            let left = self.makcu.unlock(MouseButton::Left).await;
+            let side = self.makcu.unlock(MouseButton::Side1).await;
+            let release = self.release().await;
+            left?;
+            side?;
+            release?;
         }
         Ok(())
     }
@@ -78,7 +85,7 @@ impl InputEmulator {
     pub async fn press(&self) -> anyhow::Result<()> {
         let was_pressed = self.current_press.swap(true, Ordering::AcqRel);
         if !was_pressed {
-            self.makcu.press().await?;
+            self.makcu.press(MouseButton::Left).await?;
         }
         Ok(())
     }
@@ -86,7 +93,7 @@ impl InputEmulator {
     pub async fn release(&self) -> anyhow::Result<()> {
         let was_pressed = self.current_press.swap(false, Ordering::AcqRel);
         if was_pressed {
-            self.makcu.release().await?;
+            self.makcu.release(MouseButton::Left).await?;
         }
         Ok(())
     }
@@ -96,10 +103,15 @@ impl InputEmulator {
         Ok(())
     }
 
+    pub async fn wheel(&self, delta: i32) -> anyhow::Result<()> {
+        self.makcu.wheel(delta).await?;
+        Ok(())
+    }
+
     pub async fn click(&self) -> anyhow::Result<()> {
         let current_pressed = self.current_press.load(Ordering::Acquire);
         if !current_pressed {
-            self.makcu.click().await?;
+            self.makcu.click(MouseButton::Left).await?;
         }
         Ok(())
     }
@@ -114,17 +126,17 @@ impl InputEmulator {
     }
 }
 
-async fn userpress_task(mut key_state: watch::Receiver<u8>, emulator: Arc<InputEmulator>) {
+async fn userpress_task(mut key_state: watch::Receiver<ButtonState>, emulator: Arc<InputEmulator>) {
     while key_state.changed().await.is_ok() {
         let key = *key_state.borrow();
-        let user_press = (key & 1) == 1;
+        let user_press = key.is_pressed(MouseButton::Left);
         emulator.user_press.store(user_press, Ordering::Release);
 
         if !emulator.is_locked.load(Ordering::Acquire) {
             continue;
         }
 
-        let ms1_press = key >> 3 & 1 == 1;
+        let ms1_press = key.is_pressed(MouseButton::Side1);
         let user_press = user_press || ms1_press;
         if ms1_press {
             emulator.user_press.store(user_press, Ordering::Release);
@@ -1,6 +1,4 @@
-pub type Makcu = makcu::Makcu<makcu::HighSpeed>;
-
-use std::time::Duration;
+pub type Makcu = makcu::Makcu;
 
 use axum_server::tls_rustls::RustlsConfig;
 
@@ -56,22 +54,11 @@ async fn create_server() -> anyhow::Result<Server> {
 }
 
 async fn connect_makcu() -> anyhow::Result<Makcu> {
-    let makcu = makcu::Makcu::normal()?;
-    tracing::info!("{} 연결됨", makcu.port_name());
-
-    let version_result = makcu.version().await;
-    let makcu = match version_result {
-        Ok(_) => {
-            tracing::info!("고성능 모드 활성화");
-            let r = makcu.enable_high_speed_mode().await;
-            tokio::time::sleep(Duration::from_millis(100)).await;
-            r
-        }
-        Err(_) => {
-            makcu.close().await?;
-            Makcu::high_speed()
-        }
-    }?;
+    let makcu = Makcu::builder()
+        .negotiate_high_speed(true)
+        .reconnect(makcu::ReconnectPolicy::default())
+        .build()
+        .await?;
 
     tracing::info!("{} 고성능 모드로 연결됨", makcu.port_name());
     tracing::info!("버전: {}", makcu.version().await?);
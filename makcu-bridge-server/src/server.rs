@@ -1,5 +1,6 @@
 use axum::{Router, routing::get};
 use axum_server::tls_rustls::RustlsConfig;
+use makcu::MouseButton;
 use tokio::net::UdpSocket;
 
 use crate::{
@@ -80,9 +81,9 @@ impl Server {
             }
             let key = *key_state.borrow();
 
-            if key >> 2 & 1 == 1 {
-                _ = self.makcu.release().await;
-                _ = self.makcu.unlock_ml().await;
+            if key.is_pressed(MouseButton::Middle) {
+                _ = self.makcu.release(MouseButton::Left).await;
+                _ = self.makcu.unlock(MouseButton::Left).await;
             }
         }
 
@@ -11,24 +11,27 @@ use futures_util::{
     SinkExt, StreamExt,
     stream::{SplitSink, SplitStream},
 };
+use makcu::{ButtonState, ConnectionState, watch};
 use serde::Deserialize;
-use tokio::sync::watch;
 
 use crate::{Makcu, emulator::InputEmulator};
 
 #[derive(Clone)]
 pub struct AppState {
-    key_state: watch::Receiver<u8>,
+    key_state: watch::Receiver<ButtonState>,
+    connection_state: watch::Receiver<ConnectionState>,
     emulator: Arc<InputEmulator>,
 }
 
 impl AppState {
     pub fn new(makcu: Makcu) -> Self {
         let key_state = makcu.subscribe_buttons();
+        let connection_state = makcu.connection_state();
         let emulator = InputEmulator::new(makcu);
 
         Self {
             key_state,
+            connection_state,
             emulator,
         }
     }
@@ -43,7 +46,11 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
 
     tracing::info!("클라이언트 연결됨");
 
-    let sender_task = handle_sender(sender, state.key_state.clone());
+    let sender_task = handle_sender(
+        sender,
+        state.key_state.clone(),
+        state.connection_state.clone(),
+    );
     let receiver_task = handle_receiver(receiver, state.clone());
 
     _ = tokio::try_join!(sender_task, receiver_task);
@@ -53,18 +60,41 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
     _ = state.emulator.unlock().await;
 }
 
+// 키 상태는 바이너리, 장치 연결 상태는 텍스트 메시지로 보낸다
 async fn handle_sender(
     mut sender: SplitSink<WebSocket, Message>,
-    mut key_state: watch::Receiver<u8>,
+    mut key_state: watch::Receiver<ButtonState>,
+    mut connection_state: watch::Receiver<ConnectionState>,
 ) -> anyhow::Result<()> {
+    let connection = connection_message(&connection_state.borrow_and_update());
+    sender.send(connection).await?;
+
     loop {
-        key_state.changed().await?;
-        let key = *key_state.borrow();
-        tracing::debug!("웹소켓 키 전달: {key}");
-        sender.send(Message::Binary(vec![key].into())).await?;
+        tokio::select! {
+            changed = key_state.changed() => {
+                changed?;
+                let key = key_state.borrow().bits();
+                tracing::debug!("웹소켓 키 전달: {key}");
+                sender.send(Message::Binary(vec![key].into())).await?;
+            }
+            changed = connection_state.changed() => {
+                changed?;
+                let connection = connection_message(&connection_state.borrow());
+                sender.send(connection).await?;
+            }
+        }
     }
 }
 
+fn connection_message(state: &ConnectionState) -> Message {
+    let text = match state {
+        ConnectionState::Connected => "connected",
+        ConnectionState::Reconnecting { .. } => "reconnecting",
+        ConnectionState::Disconnected(_) => "disconnected",
+    };
+    Message::Text(text.into())
+}
+
 async fn handle_receiver(
     mut receiver: SplitStream<WebSocket>,
     state: AppState,
@@ -100,30 +130,65 @@ enum Command {
 
     #[serde(rename = "4")]
     Click,
+
+    #[serde(rename = "5")]
+    Wheel { a: i32 },
 }
 
 async fn handle_message(text: &[u8], state: &AppState) -> anyhow::Result<()> {
-    if let Ok(command) = rmp_serde::from_slice::<Command>(text) {
-        match command {
-            Command::MouseMove { a, b } => {
-                tracing::debug!(x = a, y = b, "마우스 이동");
-                state.emulator.mouse_move(a, b).await?;
-            }
-            Command::Lock => {
-                tracing::debug!("lock");
-                state.emulator.lock().await?;
-            }
-            Command::Unlock => {
-                tracing::debug!("unlock");
-                state.emulator.unlock().await?;
-            }
-            Command::Pending { b } => {
-                state.emulator.pending(b).await?;
+    let Ok(command) = rmp_serde::from_slice::<Command>(text) else {
+        return Ok(());
+    };
+
+    if !state.connection_state.borrow().is_connected() {
+        // 잠금 해제와 버튼 놓기는 연결이 끊겨도 처리한다. 보내지 못해도 세션에서 지워져
+        // 다시 연결된 장치에 잠금이 되살아나지 않는다
+        if !matches!(command, Command::Unlock | Command::Pending { b: false }) {
+            tracing::debug!("장치 연결 끊김, 명령 무시");
+            return Ok(());
+        }
+        if let Err(e) = handle_command(command, state).await {
+            tracing::debug!("장치 연결 끊김, 해제 전송 실패: {e:?}");
+        }
+        return Ok(());
+    }
+
+    handle_command(command, state).await
+}
+
+async fn handle_command(command: Command, state: &AppState) -> anyhow::Result<()> {
+    match command {
+        Command::MouseMove { a, b } => {
+            let range = -makcu::MAX_MOVE_DELTA..=makcu::MAX_MOVE_DELTA;
+            if !range.contains(&a) || !range.contains(&b) {
+                tracing::warn!(x = a, y = b, "이동 범위 초과, 무시");
+                return Ok(());
             }
-            Command::Click => {
-                tracing::debug!("클릭");
-                state.emulator.click().await?;
+            tracing::debug!(x = a, y = b, "마우스 이동");
+            state.emulator.mouse_move(a, b).await?;
+        }
+        Command::Lock => {
+            tracing::debug!("lock");
+            state.emulator.lock().await?;
+        }
+        Command::Unlock => {
+            tracing::debug!("unlock");
+            state.emulator.unlock().await?;
+        }
+        Command::Pending { b } => {
+            state.emulator.pending(b).await?;
+        }
+        Command::Click => {
+            tracing::debug!("클릭");
+            state.emulator.click().await?;
+        }
+        Command::Wheel { a } => {
+            if !(-makcu::MAX_WHEEL_DELTA..=makcu::MAX_WHEEL_DELTA).contains(&a) {
+                tracing::warn!(delta = a, "휠 범위 초과, 무시");
+                return Ok(());
             }
+            tracing::debug!(delta = a, "휠");
+            state.emulator.wheel(a).await?;
         }
     }
 
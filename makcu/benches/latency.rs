@@ -0,0 +1,89 @@
+//! 명령을 보낸 순간부터 그 바이트가 장치 쪽 파이프에 도착할 때까지의 지연
+//!
+//! `polling_worker` 는 읽기와 쓰기를 한 스레드에서 번갈아 폴링하던 이전 워커를 흉내 낸
+//! 기준선이다. 같은 파이프와 같은 측정 방법으로 `command_to_wire` 와 나란히 잰다.
+
+use std::{
+    io::{Read, Write},
+    sync::mpsc::{self, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use makcu::{Makcu, Pipe, Transport, pipe};
+
+// 이전 워커가 쓰던 읽기 시간 제한. 큐가 비면 이만큼 읽고 나서야 큐를 다시 본다
+const POLL_READ_TIMEOUT: Duration = Duration::from_millis(1);
+
+// 장치 쪽 파이프에 바이트가 도착한 시각을 보낸다
+fn spawn_device(mut device: Pipe) -> mpsc::Receiver<Instant> {
+    let (arrived_tx, arrived_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = [0u8; 64];
+        while let Ok(n) = device.read(&mut buf) {
+            if n == 0 || arrived_tx.send(Instant::now()).is_err() {
+                break;
+            }
+        }
+    });
+    arrived_rx
+}
+
+fn spawn_polling_worker(mut com: Pipe) -> mpsc::SyncSender<Vec<u8>> {
+    let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(32);
+    com.set_read_timeout(POLL_READ_TIMEOUT).unwrap();
+    thread::spawn(move || {
+        let mut buf = [0u8; 64];
+        loop {
+            match rx.try_recv() {
+                Ok(data) => com.write_all(&data).unwrap(),
+                Err(TryRecvError::Empty) => {
+                    if let Ok(0) = com.read(&mut buf) {
+                        break;
+                    }
+                }
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+    });
+    tx
+}
+
+fn measure(c: &mut Criterion, name: &str, arrived_rx: &mpsc::Receiver<Instant>, send: impl Fn()) {
+    c.bench_function(name, |b| {
+        b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                let start = Instant::now();
+                send();
+                total += arrived_rx.recv().unwrap() - start;
+            }
+            total
+        })
+    });
+}
+
+fn command_to_wire(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (host, device) = pipe();
+    let makcu = Makcu::from_transport(host).unwrap();
+    let arrived_rx = spawn_device(device);
+
+    measure(c, "command_to_wire", &arrived_rx, || {
+        runtime.block_on(makcu.wheel(1)).unwrap();
+    });
+}
+
+fn polling_worker(c: &mut Criterion) {
+    let (host, device) = pipe();
+    let tx = spawn_polling_worker(host);
+    let arrived_rx = spawn_device(device);
+
+    measure(c, "polling_worker", &arrived_rx, || {
+        tx.send(b"km.wheel(1)\r".to_vec()).unwrap();
+    });
+}
+
+criterion_group!(benches, command_to_wire, polling_worker);
+criterion_main!(benches);
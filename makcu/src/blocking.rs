@@ -0,0 +1,374 @@
+//! 비동기 런타임 없이 쓰는 클라이언트. 호출한 스레드를 막고 결과를 기다린다.
+//! 보레이트 감지가 필요하면 [`crate::MakcuBuilder::build_blocking`] 으로 연다
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    BaudSwitch, ButtonEvent, ButtonState, ClickOptions, ConnectionEvent, ConnectionState, Error,
+    HIGH_SPEED_BAUD_RATE, LockTarget, MouseButton, PROBE_TIMEOUT, ReconnectPolicy, Result, Stats,
+    Transport, VERSION_COMMAND, VersionProbe, broadcast, button::ClickStep, button_command,
+    check_move, check_version, click_hold_time, detect_candidates, parse_lock_state, watch,
+    wheel_command,
+};
+
+/// [`crate::Makcu`] 와 같은 장치 핸들. 복제본끼리 연결을 공유한다
+#[derive(Clone)]
+pub struct Makcu {
+    inner: crate::Makcu,
+}
+
+impl From<crate::Makcu> for Makcu {
+    fn from(inner: crate::Makcu) -> Self {
+        Self { inner }
+    }
+}
+
+impl Makcu {
+    pub fn normal() -> Result<Self> {
+        crate::Makcu::normal().map(Self::from)
+    }
+
+    pub fn high_speed() -> Result<Self> {
+        crate::Makcu::high_speed().map(Self::from)
+    }
+
+    pub fn with_port(port_name: impl Into<String>) -> Result<Self> {
+        crate::Makcu::with_port(port_name).map(Self::from)
+    }
+
+    pub fn with_serial_number(serial_number: &str) -> Result<Self> {
+        crate::Makcu::with_serial_number(serial_number).map(Self::from)
+    }
+
+    pub fn from_transport(transport: impl Transport) -> Result<Self> {
+        crate::Makcu::from_transport(transport).map(Self::from)
+    }
+
+    /// [`crate::Makcu::from_transport_with_reconnect`] 참고
+    pub fn from_transport_with_reconnect<T, F>(
+        transport: T,
+        policy: ReconnectPolicy,
+        connect: F,
+    ) -> Result<Self>
+    where
+        T: Transport,
+        F: FnMut() -> std::io::Result<T> + Send + 'static,
+    {
+        crate::Makcu::from_transport_with_reconnect(transport, policy, connect).map(Self::from)
+    }
+
+    pub fn close(self) -> Result<()> {
+        self.inner.muxer.blocking_close()?;
+        Ok(())
+    }
+
+    pub fn port_name(&self) -> &str {
+        self.inner.port_name()
+    }
+
+    pub fn baud_rate(&self) -> u32 {
+        self.inner.baud_rate()
+    }
+
+    pub fn command_timeout(&self) -> Duration {
+        self.inner.command_timeout()
+    }
+
+    pub fn set_command_timeout(&mut self, timeout: Duration) {
+        self.inner.set_command_timeout(timeout);
+    }
+
+    pub fn coalesce_moves(&self) -> bool {
+        self.inner.coalesce_moves()
+    }
+
+    pub fn set_coalesce_moves(&self, enable: bool) {
+        self.inner.set_coalesce_moves(enable);
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.inner.stats()
+    }
+
+    pub fn ping(&self) -> Result<Duration> {
+        let start = Instant::now();
+        self.version()?;
+        Ok(start.elapsed())
+    }
+
+    pub fn version(&self) -> Result<String> {
+        let res = self.inner.muxer.blocking_write_read(VERSION_COMMAND)?;
+        Ok(res)
+    }
+
+    pub fn mouse_move(&self, x: i32, y: i32) -> Result<()> {
+        check_move(x, y)?;
+        if x != 0 || y != 0 {
+            self.inner.muxer.blocking_write_move(x, y)?;
+        }
+        Ok(())
+    }
+
+    pub fn try_mouse_move(&self, x: i32, y: i32) -> Result<()> {
+        if i8::try_from(x).is_err() || i8::try_from(y).is_err() {
+            return Err(Error::MoveOutOfRange { x, y });
+        }
+        self.inner.muxer.blocking_write_move(x, y)?;
+        Ok(())
+    }
+
+    pub fn wheel(&self, delta: i32) -> Result<()> {
+        let command = wheel_command(delta)?;
+        if !command.is_empty() {
+            self.inner.muxer.blocking_write(command)?;
+        }
+        Ok(())
+    }
+
+    pub fn click(&self, button: MouseButton) -> Result<()> {
+        self.click_with(ClickOptions::new(button).host_timed(click_hold_time()))
+    }
+
+    pub fn click_with(&self, options: ClickOptions) -> Result<()> {
+        for step in options.plan() {
+            match step {
+                ClickStep::Write(command) => self.inner.muxer.blocking_write(command)?,
+                ClickStep::Press(button) => self.press(button)?,
+                ClickStep::Release(button) => self.release(button)?,
+                ClickStep::Sleep(duration) => std::thread::sleep(duration),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn press(&self, button: MouseButton) -> Result<()> {
+        self.inner
+            .muxer
+            .blocking_write(button_command(button, true))?;
+        Ok(())
+    }
+
+    /// 큐에 밀린 이동 명령보다 먼저 보낸다. 아직 보내지 않은 누르기는 앞지르지 않는다
+    pub fn release(&self, button: MouseButton) -> Result<()> {
+        self.inner
+            .muxer
+            .blocking_write_priority(button_command(button, false))?;
+        Ok(())
+    }
+
+    pub fn lock(&self, target: impl Into<LockTarget>) -> Result<()> {
+        let target = target.into();
+        self.inner.muxer.blocking_write_session(
+            target.command_name(),
+            target.set_command(true),
+            true,
+        )?;
+        Ok(())
+    }
+
+    pub fn unlock(&self, target: impl Into<LockTarget>) -> Result<()> {
+        let target = target.into();
+        self.inner.muxer.blocking_write_session(
+            target.command_name(),
+            target.set_command(false),
+            false,
+        )?;
+        Ok(())
+    }
+
+    pub fn is_locked(&self, target: impl Into<LockTarget>) -> Result<bool> {
+        let res = self
+            .inner
+            .muxer
+            .blocking_write_read(target.into().query_command())?;
+        parse_lock_state(res)
+    }
+
+    pub fn enable_buttons(&self) -> Result<()> {
+        let command = "km.buttons(1)\r";
+        self.inner
+            .muxer
+            .blocking_write_session("buttons", command, true)?;
+        Ok(())
+    }
+
+    pub fn subscribe_buttons(&self) -> watch::Receiver<ButtonState> {
+        self.inner.subscribe_buttons()
+    }
+
+    pub fn subscribe_button_events(&self) -> broadcast::Receiver<ButtonEvent> {
+        self.inner.subscribe_button_events()
+    }
+
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.inner.connection_state()
+    }
+
+    pub fn subscribe_connection_events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.inner.subscribe_connection_events()
+    }
+}
+
+impl Makcu {
+    pub fn enable_high_speed_mode(&self) -> Result<()> {
+        self.set_baud_rate(HIGH_SPEED_BAUD_RATE)
+    }
+
+    /// 장치와 포트의 보레이트를 함께 바꾼다.
+    /// 새 보레이트에서 응답이 없으면 이전 보레이트로 되돌린다
+    pub fn set_baud_rate(&self, baud_rate: u32) -> Result<()> {
+        let switch = BaudSwitch::new(self.baud_rate(), baud_rate)?;
+        let muxer = &self.inner.muxer;
+        muxer.blocking_write(switch.frame())?;
+        muxer.blocking_set_baud_rate(switch.to)?;
+
+        if self.wait_for_version(self.inner.baud_switch_timeout) {
+            switch.succeeded();
+            return Ok(());
+        }
+
+        let error = switch.failed();
+        muxer.blocking_set_baud_rate(switch.from)?;
+        Err(error)
+    }
+
+    fn probe_version(&self, timeout: Duration) -> bool {
+        match self
+            .inner
+            .muxer
+            .blocking_write_read_timeout(VERSION_COMMAND, timeout)
+        {
+            Ok(res) => check_version(&res),
+            Err(_) => false,
+        }
+    }
+
+    fn wait_for_version(&self, window: Duration) -> bool {
+        let probe = VersionProbe::new(window);
+        loop {
+            if self.probe_version(probe.timeout()) {
+                return true;
+            }
+            let Some(interval) = probe.retry() else {
+                return false;
+            };
+            std::thread::sleep(interval);
+        }
+    }
+
+    // 현재 포트 설정으로 응답하지 않으면 후보 보레이트를 차례로 시도한다
+    pub(crate) fn detect_baud_rate(&self, candidates: &[u32]) -> Result<u32> {
+        let initial = self.baud_rate();
+        if self.probe_version(PROBE_TIMEOUT) {
+            return Ok(initial);
+        }
+        for baud_rate in detect_candidates(initial, candidates) {
+            self.inner.muxer.blocking_set_baud_rate(baud_rate)?;
+            if self.probe_version(PROBE_TIMEOUT) {
+                return Ok(baud_rate);
+            }
+        }
+        // 찾지 못하면 처음 보레이트로 되돌려 둔다
+        self.inner.muxer.blocking_set_baud_rate(initial)?;
+        Err(Error::NoVersionResponse(initial))
+    }
+}
+
+#[cfg(all(test, feature = "sim"))]
+mod tests {
+    use super::*;
+    use crate::{DEFAULT_BAUD_RATE, MuxerError, sim::Simulator};
+
+    fn connect() -> (Simulator, Makcu) {
+        let (sim, transport) = Simulator::new();
+        (sim, Makcu::from_transport(transport).unwrap())
+    }
+
+    #[test]
+    fn commands_reach_device() {
+        let (sim, makcu) = connect();
+        assert_eq!(makcu.version().unwrap(), "km.MAKCU");
+
+        makcu.mouse_move(300, -20).unwrap();
+        makcu.wheel(3).unwrap();
+        makcu.press(MouseButton::Left).unwrap();
+        makcu.version().unwrap();
+        assert_eq!(sim.position(), (300, -20));
+        assert_eq!(sim.wheel(), 3);
+        assert!(sim.host_buttons().is_pressed(MouseButton::Left));
+
+        makcu.release(MouseButton::Left).unwrap();
+        makcu.version().unwrap();
+        assert_eq!(sim.host_buttons(), ButtonState::default());
+
+        assert!(matches!(
+            makcu.mouse_move(crate::MAX_MOVE_DELTA + 1, 0),
+            Err(Error::MoveOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn lock_state() {
+        let (sim, makcu) = connect();
+        makcu.lock(LockTarget::X).unwrap();
+        assert!(makcu.is_locked(LockTarget::X).unwrap());
+        assert!(sim.is_locked(LockTarget::X));
+
+        makcu.unlock(LockTarget::X).unwrap();
+        assert!(!makcu.is_locked(LockTarget::X).unwrap());
+    }
+
+    #[test]
+    fn physical_buttons_reach_subscribers() {
+        let (sim, makcu) = connect();
+        makcu.enable_buttons().unwrap();
+        makcu.version().unwrap();
+        let mut buttons = makcu.subscribe_buttons();
+        let mut events = makcu.subscribe_button_events();
+
+        sim.press(MouseButton::Left);
+        buttons.blocking_changed().unwrap();
+        assert!(buttons.borrow_and_update().is_pressed(MouseButton::Left));
+        let event = events.blocking_recv().unwrap();
+        assert_eq!((event.button, event.pressed), (MouseButton::Left, true));
+    }
+
+    #[test]
+    fn baud_rate_switch() {
+        let (sim, makcu) = connect();
+        makcu.enable_high_speed_mode().unwrap();
+        assert_eq!(makcu.baud_rate(), HIGH_SPEED_BAUD_RATE);
+        assert_eq!(sim.baud_rate(), HIGH_SPEED_BAUD_RATE);
+        assert_eq!(makcu.version().unwrap(), "km.MAKCU");
+    }
+
+    #[test]
+    fn failed_baud_rate_switch_rolls_back() {
+        let (sim, makcu) = connect();
+        sim.ignore_baud_frames(true);
+
+        assert!(matches!(
+            makcu.set_baud_rate(HIGH_SPEED_BAUD_RATE),
+            Err(Error::BaudRateSwitchFailed {
+                from: DEFAULT_BAUD_RATE,
+                to: HIGH_SPEED_BAUD_RATE,
+            })
+        ));
+        assert_eq!(makcu.baud_rate(), DEFAULT_BAUD_RATE);
+        assert_eq!(makcu.version().unwrap(), "km.MAKCU");
+    }
+
+    #[test]
+    fn close_stops_clones() {
+        let (_sim, makcu) = connect();
+        let clone = makcu.clone();
+        let mut state = makcu.connection_state();
+        makcu.close().unwrap();
+
+        assert!(matches!(
+            clone.version(),
+            Err(Error::Muxer(MuxerError::ChannelClosed))
+        ));
+        assert!(!state.borrow_and_update().is_connected());
+    }
+}
@@ -0,0 +1,132 @@
+//! 모든 구독자에게 같은 이벤트를 나눠 주는 채널.
+//! 가득 차면 가장 오래된 이벤트를 밀어내고, 밀린 구독자는 [`RecvError::Lagged`] 를 받는다
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum RecvError {
+    #[error("broadcast channel closed")]
+    Closed,
+    #[error("receiver lagged behind by {0} events")]
+    Lagged(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum TryRecvError {
+    #[error("broadcast channel empty")]
+    Empty,
+    #[error("broadcast channel closed")]
+    Closed,
+    #[error("receiver lagged behind by {0} events")]
+    Lagged(u64),
+}
+
+impl From<async_broadcast::RecvError> for RecvError {
+    fn from(e: async_broadcast::RecvError) -> Self {
+        match e {
+            async_broadcast::RecvError::Overflowed(n) => RecvError::Lagged(n),
+            async_broadcast::RecvError::Closed => RecvError::Closed,
+        }
+    }
+}
+
+impl From<async_broadcast::TryRecvError> for TryRecvError {
+    fn from(e: async_broadcast::TryRecvError) -> Self {
+        match e {
+            async_broadcast::TryRecvError::Overflowed(n) => TryRecvError::Lagged(n),
+            async_broadcast::TryRecvError::Empty => TryRecvError::Empty,
+            async_broadcast::TryRecvError::Closed => TryRecvError::Closed,
+        }
+    }
+}
+
+pub(crate) fn channel<T: Clone>(capacity: usize) -> Sender<T> {
+    let (mut tx, rx) = async_broadcast::broadcast(capacity);
+    tx.set_overflow(true);
+    Sender {
+        tx,
+        // 구독자가 모두 떠나도 채널이 닫히지 않도록 붙잡아 둔다
+        idle: rx.deactivate(),
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Sender<T> {
+    tx: async_broadcast::Sender<T>,
+    idle: async_broadcast::InactiveReceiver<T>,
+}
+
+impl<T: Clone> Sender<T> {
+    /// 구독자가 없으면 버린다. 채널이 가득 찼다면 밀려난 이벤트를 돌려준다
+    pub fn send(&self, value: T) -> Option<T> {
+        self.tx.try_broadcast(value).ok().flatten()
+    }
+
+    /// 구독한 뒤에 보낸 이벤트부터 받는다
+    pub fn subscribe(&self) -> Receiver<T> {
+        Receiver {
+            rx: self.idle.activate_cloned(),
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    rx: async_broadcast::Receiver<T>,
+}
+
+impl<T: Clone> Receiver<T> {
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        Ok(self.rx.recv_direct().await?)
+    }
+
+    /// [`Receiver::recv`] 와 같지만 현재 스레드를 막고 기다린다
+    pub fn blocking_recv(&mut self) -> Result<T, RecvError> {
+        Ok(self.rx.recv_blocking()?)
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        Ok(self.rx.try_recv()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscriber_sees_only_later_events() {
+        let tx = channel(4);
+        // 구독자가 없어도 보낼 수 있다
+        assert_eq!(tx.send(1), None);
+
+        let mut rx = tx.subscribe();
+        tx.send(2);
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn full_channel_drops_oldest() {
+        let tx = channel(2);
+        let mut rx = tx.subscribe();
+        assert_eq!(tx.send(1), None);
+        assert_eq!(tx.send(2), None);
+        assert_eq!(tx.send(3), Some(1));
+        assert_eq!(tx.send(4), Some(2));
+
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Lagged(2)));
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(rx.blocking_recv(), Ok(4));
+    }
+
+    #[tokio::test]
+    async fn closed_after_sender_drops() {
+        let tx = channel(4);
+        let mut rx = tx.subscribe();
+        tx.send(1);
+        drop(tx);
+
+        // 남은 이벤트를 다 받은 뒤에 닫힌다
+        assert_eq!(rx.recv().await, Ok(1));
+        assert_eq!(rx.recv().await, Err(RecvError::Closed));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+    }
+}
@@ -0,0 +1,308 @@
+use std::time::Duration;
+
+use crate::{
+    DEFAULT_BAUD_RATE, DeviceFilter, HIGH_SPEED_BAUD_RATE, Makcu, ReconnectPolicy, Result,
+    Transport, find_device_by_serial_number_with, find_device_with,
+    reconnect::{Connector, Reconnect},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timeouts {
+    pub read: Duration,
+    pub command: Duration,
+    pub baud_switch: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            read: Duration::from_millis(100),
+            command: Duration::from_millis(500),
+            baud_switch: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum PortSelector {
+    Auto,
+    Port(String),
+    SerialNumber(String),
+}
+
+impl PortSelector {
+    fn resolve(&self, filter: DeviceFilter) -> Result<String> {
+        match self {
+            PortSelector::Auto => find_device_with(filter),
+            PortSelector::Port(port_name) => Ok(port_name.clone()),
+            PortSelector::SerialNumber(serial_number) => {
+                find_device_by_serial_number_with(serial_number, filter)
+            }
+        }
+    }
+}
+
+// 다시 꽂힌 장치는 포트 이름이 바뀔 수 있으므로 매번 새로 찾는다
+fn connector(port: PortSelector, filter: DeviceFilter, read_timeout: Duration) -> Connector {
+    Box::new(move || {
+        let port_name = port.resolve(filter)?;
+        let com = serialport::new(&port_name, DEFAULT_BAUD_RATE)
+            .timeout(read_timeout)
+            .open()?;
+        Ok(Box::new(com) as Box<dyn Transport>)
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct MakcuBuilder {
+    port: PortSelector,
+    filter: DeviceFilter,
+    baud_rate: u32,
+    timeouts: Timeouts,
+    target_baud_rate: Option<u32>,
+    reconnect: Option<ReconnectPolicy>,
+    coalesce_moves: bool,
+}
+
+impl Default for MakcuBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MakcuBuilder {
+    pub fn new() -> Self {
+        Self {
+            port: PortSelector::Auto,
+            filter: DeviceFilter::default(),
+            baud_rate: DEFAULT_BAUD_RATE,
+            timeouts: Timeouts::default(),
+            target_baud_rate: None,
+            reconnect: None,
+            coalesce_moves: false,
+        }
+    }
+
+    pub fn port(mut self, port_name: impl Into<String>) -> Self {
+        self.port = PortSelector::Port(port_name.into());
+        self
+    }
+
+    pub fn serial_number(mut self, serial_number: impl Into<String>) -> Self {
+        self.port = PortSelector::SerialNumber(serial_number.into());
+        self
+    }
+
+    pub fn filter(mut self, filter: DeviceFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// 처음 포트를 열 때 사용할 보레이트
+    pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    /// 읽기 스레드가 종료 요청을 확인하는 주기. 응답 지연과는 관계없다
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.read = timeout;
+        self
+    }
+
+    pub fn command_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.command = timeout;
+        self
+    }
+
+    /// 보레이트 전환 후 장치의 응답을 기다리는 최대 시간
+    pub fn baud_switch_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.baud_switch = timeout;
+        self
+    }
+
+    /// 연결 후 고성능 모드(4M)로 전환한다
+    pub fn negotiate_high_speed(mut self, enable: bool) -> Self {
+        self.target_baud_rate = enable.then_some(HIGH_SPEED_BAUD_RATE);
+        self
+    }
+
+    /// 연결 후 지정한 보레이트로 전환한다
+    pub fn target_baud_rate(mut self, baud_rate: u32) -> Self {
+        self.target_baud_rate = Some(baud_rate);
+        self
+    }
+
+    /// 장치가 뽑혔다 다시 꽂히면 자동으로 다시 연결한다
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
+    }
+
+    /// 밀린 이동 명령을 합쳐서 보낸다. [`Makcu::set_coalesce_moves`] 참고
+    pub fn coalesce_moves(mut self, enable: bool) -> Self {
+        self.coalesce_moves = enable;
+        self
+    }
+
+    // 포트를 열기만 하고 보레이트 협상은 호출자가 한다
+    fn open(&self) -> Result<Makcu> {
+        let port_name = self.port.resolve(self.filter)?;
+        let reconnect = self.reconnect.clone().map(|policy| Reconnect {
+            policy,
+            connector: connector(self.port.clone(), self.filter, self.timeouts.read),
+        });
+
+        let makcu = Makcu::open(port_name, self.baud_rate, self.timeouts, reconnect)?;
+        makcu.set_coalesce_moves(self.coalesce_moves);
+        Ok(makcu)
+    }
+
+    fn baud_rate_candidates(&self) -> Vec<u32> {
+        let mut candidates = vec![DEFAULT_BAUD_RATE, HIGH_SPEED_BAUD_RATE];
+        candidates.extend(self.target_baud_rate);
+        candidates
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn build(self) -> Result<Makcu> {
+        let makcu = self.open()?;
+        self.connect(makcu).await
+    }
+
+    #[cfg(feature = "async")]
+    async fn connect(&self, makcu: Makcu) -> Result<Makcu> {
+        let baud_rate = makcu.detect_baud_rate(&self.baud_rate_candidates()).await?;
+        tracing::debug!(port_name = makcu.port_name(), baud_rate, "보레이트 감지");
+
+        if let Some(target) = self.target_baud_rate
+            && target != baud_rate
+        {
+            makcu.set_baud_rate(target).await?;
+        }
+
+        Ok(makcu)
+    }
+
+    /// [`MakcuBuilder::build`] 의 블로킹 버전
+    #[cfg(feature = "blocking")]
+    pub fn build_blocking(self) -> Result<crate::blocking::Makcu> {
+        let makcu = crate::blocking::Makcu::from(self.open()?);
+        self.connect_blocking(makcu)
+    }
+
+    #[cfg(feature = "blocking")]
+    fn connect_blocking(&self, makcu: crate::blocking::Makcu) -> Result<crate::blocking::Makcu> {
+        let baud_rate = makcu.detect_baud_rate(&self.baud_rate_candidates())?;
+        tracing::debug!(port_name = makcu.port_name(), baud_rate, "보레이트 감지");
+
+        if let Some(target) = self.target_baud_rate
+            && target != baud_rate
+        {
+            makcu.set_baud_rate(target)?;
+        }
+
+        Ok(makcu)
+    }
+}
+
+#[cfg(all(test, feature = "sim"))]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::Error;
+    use crate::frame::Frame;
+    use crate::sim::{SimTransport, Simulator};
+
+    // 이전 연결에서 보레이트를 바꿔 둔 장치. 호스트 쪽은 기본 보레이트 그대로다
+    fn device_at(baud_rate: u32) -> (Simulator, SimTransport) {
+        let (sim, mut transport) = Simulator::new();
+        transport
+            .write_all(&Frame::SetBaudRate(baud_rate).encode())
+            .unwrap();
+        assert_eq!(sim.baud_rate(), baud_rate);
+        (sim, transport)
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn negotiates_high_speed() {
+        let (sim, transport) = Simulator::new();
+        let makcu = MakcuBuilder::new()
+            .negotiate_high_speed(true)
+            .connect(Makcu::from_transport(transport).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(makcu.baud_rate(), HIGH_SPEED_BAUD_RATE);
+        assert_eq!(sim.baud_rate(), HIGH_SPEED_BAUD_RATE);
+        assert_eq!(makcu.version().await.unwrap(), "km.MAKCU");
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn detects_device_already_at_high_speed() {
+        let (sim, transport) = device_at(HIGH_SPEED_BAUD_RATE);
+        let makcu = MakcuBuilder::new()
+            .negotiate_high_speed(true)
+            .connect(Makcu::from_transport(transport).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(makcu.baud_rate(), HIGH_SPEED_BAUD_RATE);
+        assert_eq!(sim.baud_rate(), HIGH_SPEED_BAUD_RATE);
+        assert_eq!(makcu.version().await.unwrap(), "km.MAKCU");
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn detects_device_at_target_baud_rate() {
+        let (sim, transport) = device_at(1_000_000);
+        let makcu = MakcuBuilder::new()
+            .target_baud_rate(1_000_000)
+            .connect(Makcu::from_transport(transport).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(makcu.baud_rate(), 1_000_000);
+        assert_eq!(sim.baud_rate(), 1_000_000);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn detection_failure_restores_baud_rate() {
+        let (_sim, transport) = device_at(1_000_000);
+        let makcu = Makcu::from_transport(transport).unwrap();
+        let result = MakcuBuilder::new().connect(makcu.clone()).await;
+        assert!(matches!(
+            result,
+            Err(Error::NoVersionResponse(DEFAULT_BAUD_RATE))
+        ));
+        assert_eq!(makcu.baud_rate(), DEFAULT_BAUD_RATE);
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn blocking_detects_device_already_at_high_speed() {
+        let (sim, transport) = device_at(HIGH_SPEED_BAUD_RATE);
+        let makcu = crate::blocking::Makcu::from_transport(transport).unwrap();
+        let makcu = MakcuBuilder::new()
+            .negotiate_high_speed(true)
+            .connect_blocking(makcu)
+            .unwrap();
+        assert_eq!(makcu.baud_rate(), HIGH_SPEED_BAUD_RATE);
+        assert_eq!(sim.baud_rate(), HIGH_SPEED_BAUD_RATE);
+        assert_eq!(makcu.version().unwrap(), "km.MAKCU");
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn blocking_detection_failure_restores_baud_rate() {
+        let (_sim, transport) = device_at(1_000_000);
+        let makcu = crate::blocking::Makcu::from_transport(transport).unwrap();
+        let result = MakcuBuilder::new().connect_blocking(makcu.clone());
+        assert!(matches!(
+            result,
+            Err(Error::NoVersionResponse(DEFAULT_BAUD_RATE))
+        ));
+        assert_eq!(makcu.baud_rate(), DEFAULT_BAUD_RATE);
+    }
+}
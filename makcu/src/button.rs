@@ -0,0 +1,224 @@
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Side1,
+    Side2,
+}
+
+impl MouseButton {
+    pub const ALL: [MouseButton; 5] = [
+        MouseButton::Left,
+        MouseButton::Right,
+        MouseButton::Middle,
+        MouseButton::Side1,
+        MouseButton::Side2,
+    ];
+
+    pub(crate) fn command_name(self) -> &'static str {
+        match self {
+            MouseButton::Left => "left",
+            MouseButton::Right => "right",
+            MouseButton::Middle => "middle",
+            MouseButton::Side1 => "side1",
+            MouseButton::Side2 => "side2",
+        }
+    }
+
+    // `km.click` 이 받는 버튼 번호. 왼쪽이 0
+    pub(crate) const fn index(self) -> u32 {
+        self.mask().trailing_zeros()
+    }
+
+    pub(crate) const fn mask(self) -> u8 {
+        match self {
+            MouseButton::Left => 1 << 0,
+            MouseButton::Right => 1 << 1,
+            MouseButton::Middle => 1 << 2,
+            MouseButton::Side1 => 1 << 3,
+            MouseButton::Side2 => 1 << 4,
+        }
+    }
+}
+
+/// `km.buttons()` 가 보고하는 버튼 비트마스크
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ButtonState(u8);
+
+impl ButtonState {
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn is_pressed(self, button: MouseButton) -> bool {
+        self.0 & button.mask() != 0
+    }
+
+    pub fn pressed(self) -> impl Iterator<Item = MouseButton> {
+        MouseButton::ALL
+            .into_iter()
+            .filter(move |&button| self.is_pressed(button))
+    }
+
+    /// `previous` 이후 상태가 바뀐 버튼과 현재 눌림 여부
+    pub fn diff(self, previous: ButtonState) -> impl Iterator<Item = (MouseButton, bool)> {
+        let changed = ButtonState(self.0 ^ previous.0);
+        changed
+            .pressed()
+            .map(move |button| (button, self.is_pressed(button)))
+    }
+}
+
+impl From<u8> for ButtonState {
+    fn from(bits: u8) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<ButtonState> for u8 {
+    fn from(state: ButtonState) -> Self {
+        state.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonEvent {
+    pub button: MouseButton,
+    pub pressed: bool,
+    pub timestamp: Instant,
+    /// 연속 증가하는 번호. 건너뛴 값이 있으면 수신자가 밀린 것
+    pub sequence: u64,
+}
+
+/// 누르고 떼는 간격을 누가 재는지
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClickTiming {
+    /// 장치가 `km.click` 으로 직접 누르고 뗀다. 명령 하나로 끝난다
+    #[default]
+    Device,
+    /// `km.click` 이 없는 펌웨어용. 호스트가 누르고 `hold` 만큼 기다린 뒤 뗀다
+    Host { hold: Duration },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClickOptions {
+    pub button: MouseButton,
+    pub count: u32,
+    /// 한 클릭을 뗀 뒤 다음 클릭을 누르기까지
+    pub interval: Duration,
+    pub timing: ClickTiming,
+}
+
+impl ClickOptions {
+    /// 장치가 재는 한 번 클릭
+    pub fn new(button: MouseButton) -> Self {
+        Self {
+            button,
+            count: 1,
+            interval: Duration::ZERO,
+            timing: ClickTiming::Device,
+        }
+    }
+
+    pub fn count(mut self, count: u32) -> Self {
+        self.count = count;
+        self
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// [`ClickTiming::Host`] 로 바꾼다
+    pub fn host_timed(mut self, hold: Duration) -> Self {
+        self.timing = ClickTiming::Host { hold };
+        self
+    }
+
+    /// 비동기와 블로킹 클라이언트가 같은 순서로 실행한다
+    pub(crate) fn plan(&self) -> impl Iterator<Item = ClickStep> + use<> {
+        let Self {
+            button,
+            count,
+            interval,
+            timing,
+        } = *self;
+        let device = match timing {
+            ClickTiming::Device if count > 0 => Some(ClickStep::Write(self.device_command())),
+            _ => None,
+        };
+        let host = match timing {
+            ClickTiming::Host { hold } => Some(hold),
+            ClickTiming::Device => None,
+        };
+        let clicks = host.into_iter().flat_map(move |hold| {
+            (0..count).flat_map(move |i| {
+                (i > 0)
+                    .then_some(ClickStep::Sleep(interval))
+                    .into_iter()
+                    .chain([
+                        ClickStep::Press(button),
+                        ClickStep::Sleep(hold),
+                        ClickStep::Release(button),
+                    ])
+            })
+        });
+        device.into_iter().chain(clicks)
+    }
+
+    pub(crate) fn device_command(&self) -> String {
+        format!(
+            "km.click({},{},{})\r",
+            self.button.index(),
+            self.count,
+            self.interval.as_millis()
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ClickStep {
+    Write(String),
+    Press(MouseButton),
+    Release(MouseButton),
+    Sleep(Duration),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn click_plan() {
+        let options = ClickOptions::new(MouseButton::Right).count(2);
+        assert_eq!(
+            options.plan().collect::<Vec<_>>(),
+            [ClickStep::Write("km.click(1,2,0)\r".to_owned())]
+        );
+        assert_eq!(options.count(0).plan().count(), 0);
+
+        let hold = Duration::from_millis(40);
+        let interval = Duration::from_millis(10);
+        let options = options.interval(interval).host_timed(hold);
+        assert_eq!(
+            options.plan().collect::<Vec<_>>(),
+            [
+                ClickStep::Press(MouseButton::Right),
+                ClickStep::Sleep(hold),
+                ClickStep::Release(MouseButton::Right),
+                ClickStep::Sleep(interval),
+                ClickStep::Press(MouseButton::Right),
+                ClickStep::Sleep(hold),
+                ClickStep::Release(MouseButton::Right),
+            ]
+        );
+    }
+}
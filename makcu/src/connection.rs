@@ -0,0 +1,97 @@
+use std::{io, sync::Arc};
+
+use crate::{broadcast, watch};
+
+const CONNECTION_EVENT_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone)]
+pub enum DisconnectReason {
+    /// `close` 를 호출했거나 모든 핸들이 사라졌다
+    Closed,
+    /// 입출력 오류로 연결이 끊겼고 다시 연결하지 못했다
+    Io(Arc<io::Error>),
+}
+
+#[derive(Debug, Clone)]
+pub enum ConnectionState {
+    Connected,
+    /// 재연결을 시도하는 중. `attempt` 는 1부터 센다
+    Reconnecting {
+        attempt: u32,
+    },
+    Disconnected(DisconnectReason),
+}
+
+impl ConnectionState {
+    pub fn is_connected(&self) -> bool {
+        matches!(self, ConnectionState::Connected)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ConnectionEvent {
+    /// 입출력 오류로 연결이 끊겼다
+    Lost(Arc<io::Error>),
+    ReconnectAttempt {
+        attempt: u32,
+    },
+    Reconnected {
+        attempts: u32,
+    },
+    /// 워커가 종료됐다. 이후로는 이벤트가 오지 않는다
+    Disconnected(DisconnectReason),
+}
+
+pub(crate) struct ConnectionPublisher {
+    state_tx: watch::Sender<ConnectionState>,
+    event_tx: broadcast::Sender<ConnectionEvent>,
+}
+
+impl ConnectionPublisher {
+    pub fn new() -> Self {
+        let (state_tx, _) = watch::channel(ConnectionState::Connected);
+        let event_tx = broadcast::channel(CONNECTION_EVENT_CAPACITY);
+        Self { state_tx, event_tx }
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state_tx.borrow().clone()
+    }
+
+    pub fn publish(&self, event: ConnectionEvent) {
+        let state = match &event {
+            // 다음 상태는 [`ConnectionPublisher::lost`] 가 정한다
+            ConnectionEvent::Lost(_) => None,
+            ConnectionEvent::ReconnectAttempt { attempt } => {
+                Some(ConnectionState::Reconnecting { attempt: *attempt })
+            }
+            ConnectionEvent::Reconnected { .. } => Some(ConnectionState::Connected),
+            ConnectionEvent::Disconnected(reason) => {
+                Some(ConnectionState::Disconnected(reason.clone()))
+            }
+        };
+        if let Some(state) = state {
+            self.state_tx.send_replace(state);
+        }
+        _ = self.event_tx.send(event);
+    }
+
+    /// 이벤트를 받은 구독자가 이미 끊긴 상태를 보도록 상태를 먼저 바꾼다
+    pub fn lost(&self, error: Arc<io::Error>, reconnecting: bool) {
+        let state = if reconnecting {
+            ConnectionState::Reconnecting { attempt: 1 }
+        } else {
+            ConnectionState::Disconnected(DisconnectReason::Io(Arc::clone(&error)))
+        };
+        self.state_tx.send_replace(state);
+        self.publish(ConnectionEvent::Lost(error));
+    }
+
+    pub fn subscribe_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state_tx.subscribe()
+    }
+
+    pub fn subscribe_events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.event_tx.subscribe()
+    }
+}
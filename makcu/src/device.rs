@@ -0,0 +1,106 @@
+use serialport::SerialPortType;
+
+use crate::{Error, Result};
+
+/// 장치를 찾을 때 사용하는 USB VID/PID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeviceFilter {
+    pub vid: u16,
+    pub pid: u16,
+}
+
+impl DeviceFilter {
+    pub const MAKCU: DeviceFilter = DeviceFilter {
+        vid: 0x1A86,
+        pid: 0x55D3,
+    };
+}
+
+impl Default for DeviceFilter {
+    fn default() -> Self {
+        Self::MAKCU
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub port_name: String,
+    pub vid: u16,
+    pub pid: u16,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    /// USB 버스/포트 경로 (예: `1-2.3`). 알 수 없는 플랫폼에서는 `None`
+    pub location: Option<String>,
+}
+
+pub fn list_devices() -> Result<Vec<DeviceInfo>> {
+    list_devices_with(DeviceFilter::default())
+}
+
+pub fn list_devices_with(filter: DeviceFilter) -> Result<Vec<DeviceInfo>> {
+    let devices = serialport::available_ports()?
+        .into_iter()
+        .filter_map(|port| match port.port_type {
+            SerialPortType::UsbPort(usb) if usb.vid == filter.vid && usb.pid == filter.pid => {
+                Some(DeviceInfo {
+                    location: usb_location(&port.port_name),
+                    port_name: port.port_name,
+                    vid: usb.vid,
+                    pid: usb.pid,
+                    serial_number: usb.serial_number,
+                    manufacturer: usb.manufacturer,
+                    product: usb.product,
+                })
+            }
+            _ => None,
+        })
+        .collect();
+
+    Ok(devices)
+}
+
+pub fn find_device() -> Result<String> {
+    find_device_with(DeviceFilter::default())
+}
+
+pub fn find_device_with(filter: DeviceFilter) -> Result<String> {
+    list_devices_with(filter)?
+        .into_iter()
+        .next()
+        .map(|device| device.port_name)
+        .ok_or(Error::DeviceNotFound)
+}
+
+pub fn find_device_by_serial_number(serial_number: &str) -> Result<String> {
+    find_device_by_serial_number_with(serial_number, DeviceFilter::default())
+}
+
+pub fn find_device_by_serial_number_with(
+    serial_number: &str,
+    filter: DeviceFilter,
+) -> Result<String> {
+    list_devices_with(filter)?
+        .into_iter()
+        .find(|device| device.serial_number.as_deref() == Some(serial_number))
+        .map(|device| device.port_name)
+        .ok_or(Error::DeviceNotFound)
+}
+
+#[cfg(target_os = "linux")]
+fn usb_location(port_name: &str) -> Option<String> {
+    // /sys/class/tty/ttyACM0/device -> .../usb1/1-2/1-2:1.0
+    let tty = std::path::Path::new(port_name).file_name()?;
+    let interface = std::path::Path::new("/sys/class/tty")
+        .join(tty)
+        .join("device")
+        .canonicalize()
+        .ok()?;
+    let interface = interface.file_name()?.to_str()?;
+    interface.split(':').next().map(str::to_owned)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn usb_location(_port_name: &str) -> Option<String> {
+    None
+}
@@ -0,0 +1,176 @@
+//! `0xDE 0xAD` 로 시작하는 바이너리 명령 프레임
+//!
+//! ```text
+//! magic(2) | size(2, LE) | command(1) | payload(size - 1)
+//! ```
+
+pub const MAGIC: [u8; 2] = [0xDE, 0xAD];
+pub const HEADER_LEN: usize = 4;
+
+const SET_BAUD_RATE: u8 = 0xA5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum FrameError {
+    #[error("incomplete frame")]
+    Incomplete,
+    #[error("invalid magic: {0:02X?}")]
+    InvalidMagic([u8; 2]),
+    #[error("empty frame")]
+    Empty,
+    #[error("payload too large: {0} bytes")]
+    TooLarge(usize),
+    #[error("unknown command: {0:#04X}")]
+    UnknownCommand(u8),
+    #[error("command {command:#04X} expects {expected} payload bytes, got {got}")]
+    InvalidLength {
+        command: u8,
+        expected: usize,
+        got: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frame {
+    SetBaudRate(u32),
+}
+
+impl Frame {
+    pub fn command(&self) -> u8 {
+        match self {
+            Frame::SetBaudRate(_) => SET_BAUD_RATE,
+        }
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        match self {
+            Frame::SetBaudRate(baud_rate) => baud_rate.to_le_bytes().to_vec(),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        encode_raw(self.command(), &self.payload()).expect("typed frame payload fits")
+    }
+
+    /// 버퍼 앞의 프레임 하나를 해석하고 사용한 바이트 수를 함께 돌려준다
+    pub fn decode(buf: &[u8]) -> Result<(Frame, usize), FrameError> {
+        let (command, payload, len) = decode_raw(buf)?;
+        let frame = match command {
+            SET_BAUD_RATE => {
+                let bytes: [u8; 4] = payload.try_into().map_err(|_| FrameError::InvalidLength {
+                    command,
+                    expected: 4,
+                    got: payload.len(),
+                })?;
+                Frame::SetBaudRate(u32::from_le_bytes(bytes))
+            }
+            _ => return Err(FrameError::UnknownCommand(command)),
+        };
+        Ok((frame, len))
+    }
+}
+
+pub fn encode_raw(command: u8, payload: &[u8]) -> Result<Vec<u8>, FrameError> {
+    let size = u16::try_from(payload.len() + 1).map_err(|_| FrameError::TooLarge(payload.len()))?;
+
+    let mut buf = Vec::with_capacity(HEADER_LEN + size as usize);
+    buf.extend_from_slice(&MAGIC);
+    buf.extend_from_slice(&size.to_le_bytes());
+    buf.push(command);
+    buf.extend_from_slice(payload);
+    Ok(buf)
+}
+
+pub fn decode_raw(buf: &[u8]) -> Result<(u8, &[u8], usize), FrameError> {
+    let Some(header) = buf.get(..HEADER_LEN) else {
+        return Err(FrameError::Incomplete);
+    };
+    if header[..2] != MAGIC {
+        return Err(FrameError::InvalidMagic([header[0], header[1]]));
+    }
+
+    let size = u16::from_le_bytes([header[2], header[3]]) as usize;
+    if size == 0 {
+        return Err(FrameError::Empty);
+    }
+    let Some(body) = buf.get(HEADER_LEN..HEADER_LEN + size) else {
+        return Err(FrameError::Incomplete);
+    };
+
+    Ok((body[0], &body[1..], HEADER_LEN + size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_baud_rate_matches_device_layout() {
+        let encoded = Frame::SetBaudRate(4_000_000).encode();
+        assert_eq!(
+            encoded,
+            [0xDE, 0xAD, 0x05, 0x00, 0xA5, 0x00, 0x09, 0x3D, 0x00]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for baud_rate in [115_200, 1_000_000, 2_000_000, 4_000_000] {
+            let frame = Frame::SetBaudRate(baud_rate);
+            let encoded = frame.encode();
+            assert_eq!(Frame::decode(&encoded), Ok((frame, encoded.len())));
+        }
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let encoded = encode_raw(0x42, b"payload").unwrap();
+        assert_eq!(
+            decode_raw(&encoded),
+            Ok((0x42, &b"payload"[..], encoded.len()))
+        );
+    }
+
+    #[test]
+    fn decode_leaves_trailing_bytes() {
+        let mut buf = Frame::SetBaudRate(115_200).encode();
+        buf.extend_from_slice(b"km.version()\r");
+        let (_, len) = Frame::decode(&buf).unwrap();
+        assert_eq!(&buf[len..], b"km.version()\r");
+    }
+
+    #[test]
+    fn incomplete() {
+        let encoded = Frame::SetBaudRate(115_200).encode();
+        for len in 0..encoded.len() {
+            assert_eq!(Frame::decode(&encoded[..len]), Err(FrameError::Incomplete));
+        }
+    }
+
+    #[test]
+    fn length_checks() {
+        assert_eq!(
+            decode_raw(&[0xBE, 0xEF, 0x01, 0x00, 0xA5]),
+            Err(FrameError::InvalidMagic([0xBE, 0xEF]))
+        );
+        assert_eq!(
+            decode_raw(&[0xDE, 0xAD, 0x00, 0x00]),
+            Err(FrameError::Empty)
+        );
+        assert_eq!(
+            Frame::decode(&encode_raw(SET_BAUD_RATE, &[0x00, 0x09]).unwrap()),
+            Err(FrameError::InvalidLength {
+                command: SET_BAUD_RATE,
+                expected: 4,
+                got: 2,
+            })
+        );
+        assert_eq!(
+            Frame::decode(&encode_raw(0x01, &[]).unwrap()),
+            Err(FrameError::UnknownCommand(0x01))
+        );
+        assert_eq!(
+            encode_raw(0x01, &vec![0; u16::MAX as usize]),
+            Err(FrameError::TooLarge(u16::MAX as usize))
+        );
+    }
+}
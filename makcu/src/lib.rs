@@ -1,187 +1,581 @@
-use std::{marker::PhantomData, time::Duration};
-
-use tokio::sync::watch;
-
-use crate::muxer::Muxer;
-
+#[cfg(not(any(feature = "async", feature = "blocking")))]
+compile_error!("enable at least one of the `rt-tokio`, `rt-smol` or `blocking` features");
+
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "async")]
+use crate::button::ClickStep;
+use crate::{
+    builder::Timeouts,
+    frame::Frame,
+    muxer::Muxer,
+    reconnect::{Connector, Reconnect},
+};
+
+pub use crate::{
+    builder::MakcuBuilder,
+    button::{ButtonEvent, ButtonState, ClickOptions, ClickTiming, MouseButton},
+    connection::{ConnectionEvent, ConnectionState, DisconnectReason},
+    device::{
+        DeviceFilter, DeviceInfo, find_device, find_device_by_serial_number,
+        find_device_by_serial_number_with, find_device_with, list_devices, list_devices_with,
+    },
+    muxer::Error as MuxerError,
+    reconnect::{ReconnectPolicy, WhileDisconnected},
+    stats::{LatencyHistogram, Stats},
+    transport::{Pipe, Transport, pipe},
+};
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod broadcast;
+mod builder;
+mod button;
+mod connection;
+mod device;
+pub mod frame;
 mod muxer;
+mod reconnect;
+#[cfg(feature = "async")]
+mod rt;
 mod serial;
+#[cfg(feature = "sim")]
+pub mod sim;
+mod stats;
+mod transport;
+pub mod watch;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("device not found")]
     DeviceNotFound,
+    #[error("unsupported baud rate: {0}")]
+    UnsupportedBaudRate(u32),
+    #[error("baud rate switch from {from} to {to} failed")]
+    BaudRateSwitchFailed { from: u32, to: u32 },
+    #[error("no valid version response at {0} baud")]
+    NoVersionResponse(u32),
+    #[error("move out of range: ({x}, {y})")]
+    MoveOutOfRange { x: i32, y: i32 },
+    #[error("wheel delta out of range: {0}")]
+    WheelOutOfRange(i32),
+    #[error("invalid response: {0:?}")]
+    InvalidResponse(String),
     #[error(transparent)]
     Muxer(#[from] muxer::Error),
     #[error(transparent)]
     Serial(#[from] serialport::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-pub fn find_device() -> Result<String> {
-    const VID: u16 = 0x1A86;
-    const PID: u16 = 0x55D3;
+pub fn check_version(version: &str) -> bool {
+    version == "km.MAKCU"
+}
 
-    let makcu_port =
-        serialport::available_ports()?
-            .into_iter()
-            .find(|port| match &port.port_type {
-                serialport::SerialPortType::UsbPort(usb) => usb.vid == VID && usb.pid == PID,
-                _ => false,
-            });
+/// `mouse_move` 한 번에 축마다 움직일 수 있는 최대 거리
+pub const MAX_MOVE_DELTA: i32 = 4096;
+
+/// `wheel` 한 번에 보낼 수 있는 최대 거리. 나눈 명령들이 링크를 오래 붙잡지 않도록 제한한다
+pub const MAX_WHEEL_DELTA: i32 = 1024;
+
+// 한 번에 보낼 수 있는 범위로 나눈 `km.move` 명령들
+pub(crate) fn move_command(x: i32, y: i32) -> String {
+    let mut xs = split_delta(x);
+    let mut ys = split_delta(y);
+    let mut command = String::new();
+    loop {
+        let (x, y) = match (xs.next(), ys.next()) {
+            (None, None) => break,
+            (x, y) => (x.unwrap_or(0), y.unwrap_or(0)),
+        };
+        command.push_str(&format!("km.move({x},{y})\r"));
+    }
+    command
+}
 
-    makcu_port
-        .map(|port| port.port_name)
-        .ok_or(Error::DeviceNotFound)
+fn check_move(x: i32, y: i32) -> Result<()> {
+    let range = -MAX_MOVE_DELTA..=MAX_MOVE_DELTA;
+    if !range.contains(&x) || !range.contains(&y) {
+        return Err(Error::MoveOutOfRange { x, y });
+    }
+    Ok(())
 }
 
-pub fn check_version(version: &str) -> bool {
-    return version == "km.MAKCU";
+fn wheel_command(delta: i32) -> Result<String> {
+    if !(-MAX_WHEEL_DELTA..=MAX_WHEEL_DELTA).contains(&delta) {
+        return Err(Error::WheelOutOfRange(delta));
+    }
+    Ok(split_delta(delta)
+        .map(|step| format!("km.wheel({step})\r"))
+        .collect())
 }
 
-pub trait BaudRate {
-    const BAUD_RATE: u32;
+fn button_command(button: MouseButton, down: bool) -> String {
+    format!("km.{}({})\r", button.command_name(), u8::from(down))
 }
 
-#[derive(Clone)]
-pub struct Normal;
+// 사람이 누르는 것처럼 30~70ms 사이로 누르고 있는다
+fn click_hold_time() -> Duration {
+    Duration::from_millis(rand::random_range(30..70))
+}
 
-#[derive(Clone)]
-pub struct HighSpeed;
+fn parse_lock_state(res: String) -> Result<bool> {
+    match res.as_str() {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(Error::InvalidResponse(res)),
+    }
+}
+
+fn check_baud_rate(baud_rate: u32) -> Result<()> {
+    if !(MIN_BAUD_RATE..=MAX_BAUD_RATE).contains(&baud_rate) {
+        return Err(Error::UnsupportedBaudRate(baud_rate));
+    }
+    Ok(())
+}
+
+fn split_delta(mut delta: i32) -> impl Iterator<Item = i32> {
+    std::iter::from_fn(move || {
+        if delta == 0 {
+            return None;
+        }
+        let step = delta.clamp(i8::MIN as i32, i8::MAX as i32);
+        delta -= step;
+        Some(step)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockTarget {
+    X,
+    Y,
+    Button(MouseButton),
+}
+
+impl LockTarget {
+    pub const ALL: [LockTarget; 7] = [
+        LockTarget::X,
+        LockTarget::Y,
+        LockTarget::Button(MouseButton::Left),
+        LockTarget::Button(MouseButton::Right),
+        LockTarget::Button(MouseButton::Middle),
+        LockTarget::Button(MouseButton::Side1),
+        LockTarget::Button(MouseButton::Side2),
+    ];
+
+    fn command_name(self) -> &'static str {
+        match self {
+            LockTarget::X => "lock_mx",
+            LockTarget::Y => "lock_my",
+            LockTarget::Button(MouseButton::Left) => "lock_ml",
+            LockTarget::Button(MouseButton::Right) => "lock_mr",
+            LockTarget::Button(MouseButton::Middle) => "lock_mm",
+            LockTarget::Button(MouseButton::Side1) => "lock_ms1",
+            LockTarget::Button(MouseButton::Side2) => "lock_ms2",
+        }
+    }
+
+    fn set_command(self, active: bool) -> String {
+        format!("km.{}({})\r", self.command_name(), u8::from(active))
+    }
+
+    fn query_command(self) -> String {
+        format!("km.{}()\r", self.command_name())
+    }
+}
+
+impl From<MouseButton> for LockTarget {
+    fn from(button: MouseButton) -> Self {
+        LockTarget::Button(button)
+    }
+}
+
+pub const DEFAULT_BAUD_RATE: u32 = 115_200;
+pub const HIGH_SPEED_BAUD_RATE: u32 = 4_000_000;
+pub const MIN_BAUD_RATE: u32 = DEFAULT_BAUD_RATE;
+pub const MAX_BAUD_RATE: u32 = HIGH_SPEED_BAUD_RATE;
+
+const VERSION_COMMAND: &str = "km.version()\r";
+const PROBE_TIMEOUT: Duration = Duration::from_millis(100);
+const PROBE_INTERVAL: Duration = Duration::from_millis(20);
+
+// 보레이트를 바꾼 뒤 `window` 안에서 버전 응답을 다시 물어보는 일정
+struct VersionProbe {
+    deadline: Instant,
+}
+
+impl VersionProbe {
+    fn new(window: Duration) -> Self {
+        Self {
+            deadline: Instant::now() + window,
+        }
+    }
 
-impl BaudRate for Normal {
-    const BAUD_RATE: u32 = 115_200;
+    fn timeout(&self) -> Duration {
+        PROBE_TIMEOUT.min(self.deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// 다시 물어볼 시간이 남았다면 그 전에 쉴 시간
+    fn retry(&self) -> Option<Duration> {
+        (Instant::now() + PROBE_INTERVAL < self.deadline).then_some(PROBE_INTERVAL)
+    }
 }
 
-impl BaudRate for HighSpeed {
-    const BAUD_RATE: u32 = 4_000_000;
+struct BaudSwitch {
+    from: u32,
+    to: u32,
+}
+
+impl BaudSwitch {
+    fn new(from: u32, to: u32) -> Result<Self> {
+        check_baud_rate(to)?;
+        Ok(Self { from, to })
+    }
+
+    fn frame(&self) -> Vec<u8> {
+        Frame::SetBaudRate(self.to).encode()
+    }
+
+    fn succeeded(&self) {
+        tracing::debug!(from = self.from, to = self.to, "보레이트 전환");
+    }
+
+    /// 이전 보레이트로 되돌린 뒤 돌려줄 오류
+    fn failed(&self) -> Error {
+        tracing::warn!(from = self.from, to = self.to, "보레이트 전환 실패, 복구");
+        Error::BaudRateSwitchFailed {
+            from: self.from,
+            to: self.to,
+        }
+    }
+}
+
+// 지금 보레이트는 이미 확인했으므로 빼고, 같은 값은 한 번만 시도한다
+fn detect_candidates(initial: u32, candidates: &[u32]) -> Vec<u32> {
+    let mut tried = vec![initial];
+    candidates
+        .iter()
+        .copied()
+        .filter(|baud_rate| {
+            let new = !tried.contains(baud_rate);
+            if new {
+                tried.push(*baud_rate);
+            }
+            new
+        })
+        .collect()
 }
 
 #[derive(Clone)]
-pub struct Makcu<B: BaudRate> {
+pub struct Makcu {
     port_name: String,
     muxer: Muxer,
-    _b: PhantomData<B>,
+    baud_switch_timeout: Duration,
 }
 
-impl<B: BaudRate> Makcu<B> {
-    fn from_port(port_name: impl Into<String>) -> Result<Self> {
-        let port_name = port_name.into();
-        let builder = serialport::new(&port_name, B::BAUD_RATE).timeout(Duration::from_millis(1));
-        tracing::debug!(port_name, baud_rate = B::BAUD_RATE, "시리얼 연결");
-        let com = builder.open()?;
-        let muxer = Muxer::new(com);
+impl Makcu {
+    pub fn builder() -> MakcuBuilder {
+        MakcuBuilder::new()
+    }
+
+    pub fn normal() -> Result<Self> {
+        let port_name = find_device()?;
+        Makcu::from_port(port_name, DEFAULT_BAUD_RATE)
+    }
+
+    pub fn high_speed() -> Result<Self> {
+        let port_name = find_device()?;
+        Makcu::from_port(port_name, HIGH_SPEED_BAUD_RATE)
+    }
+
+    pub fn with_port(port_name: impl Into<String>) -> Result<Self> {
+        Makcu::from_port(port_name, DEFAULT_BAUD_RATE)
+    }
+
+    pub fn with_serial_number(serial_number: &str) -> Result<Self> {
+        let port_name = find_device_by_serial_number(serial_number)?;
+        Makcu::from_port(port_name, DEFAULT_BAUD_RATE)
+    }
+
+    fn from_port(port_name: impl Into<String>, baud_rate: u32) -> Result<Self> {
+        Makcu::open(port_name.into(), baud_rate, Timeouts::default(), None)
+    }
+
+    fn open(
+        port_name: String,
+        baud_rate: u32,
+        timeouts: Timeouts,
+        reconnect: Option<Reconnect>,
+    ) -> Result<Self> {
+        tracing::debug!(port_name, baud_rate, "시리얼 연결");
+        let com = serialport::new(&port_name, baud_rate)
+            .timeout(timeouts.read)
+            .open()?;
 
         Ok(Self {
             port_name,
-            muxer,
-            _b: PhantomData,
+            muxer: Muxer::new(Box::new(com), baud_rate, timeouts.command, reconnect)?,
+            baud_switch_timeout: timeouts.baud_switch,
         })
     }
 
+    pub fn from_transport(transport: impl Transport) -> Result<Self> {
+        Makcu::from_boxed_transport(Box::new(transport), None)
+    }
+
+    /// 연결이 끊기면 `connect` 로 새 트랜스포트를 얻어 다시 연결한다.
+    /// 새 트랜스포트는 기본 보레이트로 열려 있어야 한다
+    pub fn from_transport_with_reconnect<T, F>(
+        transport: T,
+        policy: ReconnectPolicy,
+        mut connect: F,
+    ) -> Result<Self>
+    where
+        T: Transport,
+        F: FnMut() -> std::io::Result<T> + Send + 'static,
+    {
+        let read_timeout = Timeouts::default().read;
+        let connector: Connector = Box::new(move || {
+            let mut transport = connect()?;
+            transport.set_read_timeout(read_timeout)?;
+            Ok(Box::new(transport) as Box<dyn Transport>)
+        });
+        let reconnect = Reconnect { policy, connector };
+        Makcu::from_boxed_transport(Box::new(transport), Some(reconnect))
+    }
+
+    fn from_boxed_transport(
+        mut transport: Box<dyn Transport>,
+        reconnect: Option<Reconnect>,
+    ) -> Result<Self> {
+        let timeouts = Timeouts::default();
+        transport.set_read_timeout(timeouts.read)?;
+        let port_name = transport.name().unwrap_or_default();
+        tracing::debug!(port_name, "트랜스포트 연결");
+
+        Ok(Self {
+            port_name,
+            muxer: Muxer::new(transport, DEFAULT_BAUD_RATE, timeouts.command, reconnect)?,
+            baud_switch_timeout: timeouts.baud_switch,
+        })
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    pub fn baud_rate(&self) -> u32 {
+        self.muxer.baud_rate()
+    }
+
+    pub fn command_timeout(&self) -> Duration {
+        self.muxer.command_timeout()
+    }
+
+    /// 이 핸들에서 보내는 응답 대기 명령의 제한 시간. 복제본에는 영향이 없다
+    pub fn set_command_timeout(&mut self, timeout: Duration) {
+        self.muxer.set_command_timeout(timeout);
+    }
+
+    pub fn coalesce_moves(&self) -> bool {
+        self.muxer.coalesce_moves()
+    }
+
+    /// 켜면 아직 보내지 못하고 쌓인 이동 명령을 합쳐서 보낸다.
+    /// 이동 거리의 합은 유지되고, 다른 명령을 건너 합치지는 않는다.
+    /// 모든 복제본에 적용된다
+    pub fn set_coalesce_moves(&self, enable: bool) {
+        self.muxer.set_coalesce_moves(enable);
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.muxer.stats()
+    }
+
+    pub fn subscribe_buttons(&self) -> watch::Receiver<ButtonState> {
+        self.muxer.subscribe_buttons()
+    }
+
+    pub fn subscribe_button_events(&self) -> broadcast::Receiver<ButtonEvent> {
+        self.muxer.subscribe_button_events()
+    }
+
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.muxer.connection_state()
+    }
+
+    pub fn subscribe_connection_events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.muxer.subscribe_connection_events()
+    }
+}
+
+#[cfg(feature = "async")]
+impl Makcu {
     pub async fn close(self) -> Result<()> {
         self.muxer.close().await?;
         Ok(())
     }
 
-    pub fn port_name(&self) -> &str {
-        &self.port_name
+    /// 명령 하나를 보내고 응답이 올 때까지의 왕복 시간
+    pub async fn ping(&self) -> Result<Duration> {
+        let start = Instant::now();
+        self.version().await?;
+        Ok(start.elapsed())
     }
 
     pub async fn version(&self) -> Result<String> {
-        let command = "km.version()\r";
-
-        let res = self.muxer.write_read(command).await?;
+        let res = self.muxer.write_read(VERSION_COMMAND).await?;
         Ok(res)
     }
 
     pub async fn mouse_move(&self, x: i32, y: i32) -> Result<()> {
-        let x = x.clamp(i8::MIN as i32, i8::MAX as i32);
-        let y = y.clamp(i8::MIN as i32, i8::MAX as i32);
-        let command = format!("km.move({x},{y})\r");
-        self.muxer.write(command).await?;
+        check_move(x, y)?;
+        if x != 0 || y != 0 {
+            self.muxer.write_move(x, y).await?;
+        }
         Ok(())
     }
 
-    pub async fn click(&self) -> Result<()> {
-        self.press().await?;
-        let sleep_time = rand::random_range(30..70);
-        tokio::time::sleep(Duration::from_millis(sleep_time)).await;
-        self.release().await?;
+    pub async fn try_mouse_move(&self, x: i32, y: i32) -> Result<()> {
+        if i8::try_from(x).is_err() || i8::try_from(y).is_err() {
+            return Err(Error::MoveOutOfRange { x, y });
+        }
+        self.muxer.write_move(x, y).await?;
         Ok(())
     }
 
-    pub async fn press(&self) -> Result<()> {
-        let down = "km.left(1)\r";
-        self.muxer.write(down).await?;
+    pub async fn wheel(&self, delta: i32) -> Result<()> {
+        let command = wheel_command(delta)?;
+        if !command.is_empty() {
+            self.muxer.write(command).await?;
+        }
         Ok(())
     }
 
-    pub async fn release(&self) -> Result<()> {
-        let up: &'static str = "km.left(0)\r";
-        self.muxer.write(up).await?;
+    pub async fn click(&self, button: MouseButton) -> Result<()> {
+        self.click_with(ClickOptions::new(button).host_timed(click_hold_time()))
+            .await
+    }
+
+    /// `options.count` 번 클릭한다. 기본은 장치가 간격을 재는 `km.click` 이다
+    pub async fn click_with(&self, options: ClickOptions) -> Result<()> {
+        for step in options.plan() {
+            match step {
+                ClickStep::Write(command) => self.muxer.write(command).await?,
+                ClickStep::Press(button) => self.press(button).await?,
+                ClickStep::Release(button) => self.release(button).await?,
+                ClickStep::Sleep(duration) => rt::sleep(duration).await,
+            }
+        }
         Ok(())
     }
 
-    pub async fn lock_ml(&self) -> Result<()> {
-        let command = "km.lock_ml(1)\r";
-        self.muxer.write(command).await?;
+    pub async fn press(&self, button: MouseButton) -> Result<()> {
+        self.muxer.write(button_command(button, true)).await?;
         Ok(())
     }
 
-    pub async fn unlock_ml(&self) -> Result<()> {
-        let command = "km.lock_ml(0)\r";
-        self.muxer.write(command).await?;
+    /// 큐에 밀린 이동 명령보다 먼저 보낸다. 아직 보내지 않은 누르기는 앞지르지 않는다
+    pub async fn release(&self, button: MouseButton) -> Result<()> {
+        self.muxer
+            .write_priority(button_command(button, false))
+            .await?;
         Ok(())
     }
 
-    pub async fn lock_ms1(&self) -> Result<()> {
-        let command = "km.lock_ms1(1)\r";
-        self.muxer.write(command).await?;
+    pub async fn lock(&self, target: impl Into<LockTarget>) -> Result<()> {
+        let target = target.into();
+        self.muxer
+            .write_session(target.command_name(), target.set_command(true), true)
+            .await?;
         Ok(())
     }
 
-    pub async fn unlock_ms1(&self) -> Result<()> {
-        let command = "km.lock_ms1(0)\r";
-        self.muxer.write(command).await?;
+    /// [`Makcu::release`] 처럼 밀린 이동 명령보다 먼저 보낸다
+    pub async fn unlock(&self, target: impl Into<LockTarget>) -> Result<()> {
+        let target = target.into();
+        self.muxer
+            .write_session(target.command_name(), target.set_command(false), false)
+            .await?;
         Ok(())
     }
 
+    pub async fn is_locked(&self, target: impl Into<LockTarget>) -> Result<bool> {
+        let res = self.muxer.write_read(target.into().query_command()).await?;
+        parse_lock_state(res)
+    }
+
     pub async fn enable_buttons(&self) -> Result<()> {
         let command = "km.buttons(1)\r";
-        self.muxer.write(command).await?;
+        self.muxer.write_session("buttons", command, true).await?;
         Ok(())
     }
+}
 
-    pub fn subscribe_buttons(&self) -> watch::Receiver<u8> {
-        self.muxer.subscribe_buttons()
+#[cfg(feature = "async")]
+impl Makcu {
+    pub async fn enable_high_speed_mode(&self) -> Result<()> {
+        self.set_baud_rate(HIGH_SPEED_BAUD_RATE).await
     }
-}
 
-impl Makcu<Normal> {
-    pub fn normal() -> Result<Self> {
-        let port_name = find_device()?;
-        Makcu::from_port(port_name)
+    /// 장치와 포트의 보레이트를 함께 바꾼다.
+    /// 새 보레이트에서 응답이 없으면 이전 보레이트로 되돌린다
+    pub async fn set_baud_rate(&self, baud_rate: u32) -> Result<()> {
+        let switch = BaudSwitch::new(self.baud_rate(), baud_rate)?;
+        self.muxer.write(switch.frame()).await?;
+        self.muxer.set_baud_rate(switch.to).await?;
+
+        if self.wait_for_version(self.baud_switch_timeout).await {
+            switch.succeeded();
+            return Ok(());
+        }
+
+        let error = switch.failed();
+        self.muxer.set_baud_rate(switch.from).await?;
+        Err(error)
     }
 
-    pub async fn enable_high_speed_mode(self) -> Result<Makcu<HighSpeed>> {
-        let command = [
-            0xDE, 0xAD, // magic
-            0x05, 0x00, // size
-            0xA5, // command
-            0x00, 0x09, 0x3D, 0x00, // baud rate 4M (little-endian)
-        ];
-        self.muxer.write(command).await?;
-        self.muxer.close().await?;
+    async fn probe_version(&self, timeout: Duration) -> bool {
+        match self
+            .muxer
+            .write_read_timeout(VERSION_COMMAND, timeout)
+            .await
+        {
+            Ok(res) => check_version(&res),
+            Err(_) => false,
+        }
+    }
 
-        Makcu::from_port(self.port_name)
+    async fn wait_for_version(&self, window: Duration) -> bool {
+        let probe = VersionProbe::new(window);
+        loop {
+            if self.probe_version(probe.timeout()).await {
+                return true;
+            }
+            let Some(interval) = probe.retry() else {
+                return false;
+            };
+            rt::sleep(interval).await;
+        }
     }
-}
 
-impl Makcu<HighSpeed> {
-    pub fn high_speed() -> Result<Self> {
-        let port_name = find_device()?;
-        Makcu::from_port(port_name)
+    // 현재 포트 설정으로 응답하지 않으면 후보 보레이트를 차례로 시도한다
+    async fn detect_baud_rate(&self, candidates: &[u32]) -> Result<u32> {
+        let initial = self.baud_rate();
+        if self.probe_version(PROBE_TIMEOUT).await {
+            return Ok(initial);
+        }
+        for baud_rate in detect_candidates(initial, candidates) {
+            self.muxer.set_baud_rate(baud_rate).await?;
+            if self.probe_version(PROBE_TIMEOUT).await {
+                return Ok(baud_rate);
+            }
+        }
+        // 찾지 못하면 처음 보레이트로 되돌려 둔다
+        self.muxer.set_baud_rate(initial).await?;
+        Err(Error::NoVersionResponse(initial))
     }
 }
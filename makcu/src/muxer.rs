@@ -1,18 +1,69 @@
-use serialport::SerialPort;
-use tokio::sync::{mpsc, oneshot, watch};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::ErrorKind,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
-use crate::serial::{serial_read, serial_write};
+use crate::{
+    ButtonEvent, ButtonState, DEFAULT_BAUD_RATE, broadcast, check_version,
+    connection::{ConnectionEvent, ConnectionPublisher, ConnectionState, DisconnectReason},
+    frame::{self, Frame},
+    move_command,
+    reconnect::{Reconnect, WhileDisconnected},
+    serial::{ResponseBuffer, serial_read, serial_write},
+    stats::{Counters, Stats},
+    transport::Transport,
+    watch,
+};
 
 #[derive(Debug)]
 enum Command {
     Write {
         data: Vec<u8>,
     },
+    Move {
+        x: i32,
+        y: i32,
+    },
     WriteRead {
         data: Vec<u8>,
-        tx: oneshot::Sender<String>,
+        tx: Reply<Result<String>>,
+    },
+    SetBaudRate {
+        baud_rate: u32,
+        tx: Reply<Result<()>>,
     },
-    Close,
+    Close {
+        tx: Reply<()>,
+    },
+}
+
+// 응답 하나를 돌려받는 채널. 비동기와 블로킹 호출자가 같이 쓴다
+type Reply<T> = flume::Sender<T>;
+
+fn reply<T>() -> (Reply<T>, flume::Receiver<T>) {
+    flume::bounded(1)
+}
+
+/// 큐에 들어간 시각을 함께 기록한 명령
+#[derive(Debug)]
+struct Queued {
+    cmd: Command,
+    enqueued: Instant,
+}
+
+impl Queued {
+    fn new(cmd: Command) -> Self {
+        Self {
+            cmd,
+            enqueued: Instant::now(),
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -21,12 +72,31 @@ pub enum Error {
     IoTimeout,
     #[error("channel closed")]
     ChannelClosed,
+    #[error("no response within the command timeout")]
+    ResponseTimeout,
+    #[error("device disconnected")]
+    DeviceDisconnected,
+    #[error("baud rate changed before the response arrived")]
+    BaudRateChanged,
+    #[error("serial worker failed: {0}")]
+    WorkerFailed(Arc<std::io::Error>),
+    #[error("unexpected response: expected {expected:?}, got {got:?}")]
+    UnexpectedResponse { expected: String, got: String },
     #[error(transparent)]
     Io(std::io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    fn into_io(self) -> std::io::Error {
+        match self {
+            Error::Io(e) => e,
+            e => std::io::Error::other(e),
+        }
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
         match e.kind() {
@@ -36,131 +106,1157 @@ impl From<std::io::Error> for Error {
     }
 }
 
-impl From<mpsc::error::SendError<Command>> for Error {
-    fn from(_: mpsc::error::SendError<Command>) -> Self {
-        Error::ChannelClosed
-    }
-}
+const BUTTON_EVENT_CAPACITY: usize = 256;
+const COMMAND_QUEUE_CAPACITY: usize = 32;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+const HANDSHAKE_INTERVAL: Duration = Duration::from_millis(50);
 
-impl From<oneshot::error::RecvError> for Error {
-    fn from(_: oneshot::error::RecvError) -> Self {
-        Error::ChannelClosed
-    }
+struct Shared {
+    baud_rate: AtomicU32,
+    coalesce_moves: AtomicBool,
+    connection: ConnectionPublisher,
+    while_disconnected: WhileDisconnected,
+    // 재연결 후 다시 보내야 하는 명령 (잠금, 버튼 보고 등)
+    session: Mutex<BTreeMap<String, Vec<u8>>>,
+    stats: Arc<Counters>,
 }
 
 #[derive(Clone)]
 pub(crate) struct Muxer {
-    tx: mpsc::Sender<Command>,
-    watch_tx: watch::Sender<u8>,
+    tx: flume::Sender<Queued>,
+    // 해제와 종료처럼 밀린 이동보다 먼저 보내야 하는 명령
+    priority_tx: flume::Sender<Queued>,
+    watch_tx: watch::Sender<ButtonState>,
+    event_tx: broadcast::Sender<ButtonEvent>,
+    shared: Arc<Shared>,
+    command_timeout: Duration,
+}
+
+impl Muxer {
+    pub fn new(
+        com: Box<dyn Transport>,
+        baud_rate: u32,
+        command_timeout: Duration,
+        reconnect: Option<Reconnect>,
+    ) -> Result<Self> {
+        let (tx, rx) = flume::bounded(COMMAND_QUEUE_CAPACITY);
+        let (priority_tx, priority_rx) = flume::bounded(COMMAND_QUEUE_CAPACITY);
+        let (watch_tx, _) = watch::channel(ButtonState::default());
+        let event_tx = broadcast::channel(BUTTON_EVENT_CAPACITY);
+
+        let stats = Arc::new(Counters::default());
+        let buttons = ButtonPublisher {
+            watch_tx: watch_tx.clone(),
+            event_tx: event_tx.clone(),
+            sequence: 0,
+            stats: Arc::clone(&stats),
+        };
+        let shared = Arc::new(Shared {
+            baud_rate: AtomicU32::new(baud_rate),
+            coalesce_moves: AtomicBool::new(false),
+            connection: ConnectionPublisher::new(),
+            while_disconnected: reconnect
+                .as_ref()
+                .map(|reconnect| reconnect.policy.while_disconnected)
+                .unwrap_or_default(),
+            session: Mutex::new(BTreeMap::new()),
+            stats,
+        });
+        spawn_serial_worker(
+            com,
+            rx,
+            priority_rx,
+            buttons,
+            Arc::clone(&shared),
+            reconnect,
+        )?;
+
+        Ok(Self {
+            tx,
+            priority_tx,
+            watch_tx,
+            event_tx,
+            shared,
+            command_timeout,
+        })
+    }
+
+    pub fn command_timeout(&self) -> Duration {
+        self.command_timeout
+    }
+
+    pub fn set_command_timeout(&mut self, timeout: Duration) {
+        self.command_timeout = timeout;
+    }
+
+    // 재연결 중 거부 정책을 확인하고 큐에 넣은 시각을 기록한다
+    fn enqueue(&self, cmd: Command) -> Result<Queued> {
+        if self.shared.while_disconnected == WhileDisconnected::Reject
+            && matches!(
+                self.shared.connection.state(),
+                ConnectionState::Reconnecting { .. }
+            )
+        {
+            return Err(Error::DeviceDisconnected);
+        }
+        Ok(Queued::new(cmd))
+    }
+
+    // 워커가 입출력 오류로 종료됐다면 그 원인을 돌려준다
+    fn closed_error(&self) -> Error {
+        match self.shared.connection.state() {
+            ConnectionState::Disconnected(DisconnectReason::Closed) => Error::ChannelClosed,
+            ConnectionState::Disconnected(DisconnectReason::Io(e)) => Error::WorkerFailed(e),
+            _ => Error::DeviceDisconnected,
+        }
+    }
+
+    // `data` 가 없으면 `key` 에 해당하는 상태를 지운다
+    fn update_session(&self, key: String, data: Option<Vec<u8>>) {
+        let mut session = self.shared.session.lock().unwrap();
+        match data {
+            Some(data) => session.insert(key, data),
+            None => session.remove(&key),
+        };
+    }
+
+    fn response(
+        &self,
+        res: std::result::Result<Result<String>, flume::RecvTimeoutError>,
+    ) -> Result<String> {
+        match res {
+            Ok(response) => response,
+            Err(flume::RecvTimeoutError::Disconnected) => Err(self.closed_error()),
+            Err(flume::RecvTimeoutError::Timeout) => Err(Error::ResponseTimeout),
+        }
+    }
+
+    pub fn coalesce_moves(&self) -> bool {
+        self.shared.coalesce_moves.load(Ordering::Relaxed)
+    }
+
+    pub fn set_coalesce_moves(&self, enable: bool) {
+        self.shared.coalesce_moves.store(enable, Ordering::Relaxed);
+    }
+
+    pub fn baud_rate(&self) -> u32 {
+        self.shared.baud_rate.load(Ordering::Acquire)
+    }
+
+    pub fn stats(&self) -> Stats {
+        let queue_depth = self.tx.len() + self.priority_tx.len();
+        self.shared.stats.snapshot(queue_depth)
+    }
+
+    pub fn subscribe_buttons(&self) -> watch::Receiver<ButtonState> {
+        self.watch_tx.subscribe()
+    }
+
+    pub fn subscribe_button_events(&self) -> broadcast::Receiver<ButtonEvent> {
+        self.event_tx.subscribe()
+    }
+
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.shared.connection.subscribe_state()
+    }
+
+    pub fn subscribe_connection_events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.shared.connection.subscribe_events()
+    }
 }
 
+#[cfg(feature = "async")]
 impl Muxer {
-    pub fn new(com: Box<dyn SerialPort>) -> Self {
-        let (tx, rx) = mpsc::channel(32);
-        let (watch_tx, _) = watch::channel(0);
+    async fn send(&self, cmd: Command) -> Result<()> {
+        self.send_to(&self.tx, cmd).await
+    }
 
-        spawn_serial_worker(com, rx, watch_tx.clone());
+    async fn send_priority(&self, cmd: Command) -> Result<()> {
+        self.send_to(&self.priority_tx, cmd).await
+    }
 
-        Self { tx, watch_tx }
+    async fn send_to(&self, tx: &flume::Sender<Queued>, cmd: Command) -> Result<()> {
+        let queued = self.enqueue(cmd)?;
+        tx.send_async(queued).await.map_err(|_| self.closed_error())
     }
 
     pub async fn write(&self, data: impl Into<Vec<u8>>) -> Result<()> {
-        self.tx.send(Command::Write { data: data.into() }).await?;
+        self.send(Command::Write { data: data.into() }).await
+    }
+
+    /// 큐에 쌓인 이동 명령을 앞질러 보낸다
+    pub async fn write_priority(&self, data: impl Into<Vec<u8>>) -> Result<()> {
+        self.send_priority(Command::Write { data: data.into() })
+            .await
+    }
+
+    pub async fn write_move(&self, x: i32, y: i32) -> Result<()> {
+        self.send(Command::Move { x, y }).await
+    }
+
+    /// 재연결 후에도 유지되어야 하는 상태를 바꾸는 명령을 보낸다.
+    /// `active` 가 거짓이면 `key` 에 해당하는 상태를 지우고, 해제 명령은 우선 보낸다
+    pub async fn write_session(
+        &self,
+        key: impl Into<String>,
+        data: impl Into<Vec<u8>>,
+        active: bool,
+    ) -> Result<()> {
+        let data = data.into();
+        if !active {
+            // 해제는 보내지 못해도 지운다. 남겨 두면 다시 연결된 장치에 되살아난다
+            self.update_session(key.into(), None);
+            return self.write_priority(data).await;
+        }
+
+        self.write(data.clone()).await?;
+        self.update_session(key.into(), Some(data));
         Ok(())
     }
 
     pub async fn write_read(&self, data: impl Into<Vec<u8>>) -> Result<String> {
-        let (tx, rx) = oneshot::channel();
-        self.tx
-            .send(Command::WriteRead {
-                data: data.into(),
-                tx,
-            })
-            .await?;
+        self.write_read_timeout(data, self.command_timeout).await
+    }
+
+    pub async fn write_read_timeout(
+        &self,
+        data: impl Into<Vec<u8>>,
+        timeout: Duration,
+    ) -> Result<String> {
+        let (tx, rx) = reply();
+        self.send(Command::WriteRead {
+            data: data.into(),
+            tx,
+        })
+        .await?;
 
-        let response = rx.await?;
-        Ok(response)
+        let res = match crate::rt::timeout(timeout, rx.recv_async()).await {
+            Some(res) => res.map_err(|_| flume::RecvTimeoutError::Disconnected),
+            None => Err(flume::RecvTimeoutError::Timeout),
+        };
+        self.response(res)
     }
 
-    pub fn subscribe_buttons(&self) -> watch::Receiver<u8> {
-        self.watch_tx.subscribe()
+    // 장치에 보레이트 변경을 알리는 것은 호출자의 몫. 여기서는 포트만 바꾼다
+    pub async fn set_baud_rate(&self, baud_rate: u32) -> Result<()> {
+        let (tx, rx) = reply();
+        self.send(Command::SetBaudRate { baud_rate, tx }).await?;
+        rx.recv_async().await.map_err(|_| self.closed_error())??;
+        self.shared.baud_rate.store(baud_rate, Ordering::Release);
+        Ok(())
     }
 
     pub async fn close(&self) -> Result<()> {
-        self.tx.send(Command::Close).await?;
-        self.tx.closed().await;
+        let (tx, rx) = reply();
+        // 재연결 중에도 닫기는 거절하지 않는다
+        self.priority_tx
+            .send_async(Queued::new(Command::Close { tx }))
+            .await
+            .map_err(|_| self.closed_error())?;
+        // 워커가 포트를 놓을 때까지 기다린다
+        _ = rx.recv_async().await;
+        Ok(())
+    }
+}
+
+/// 비동기 메서드와 같은 일을 하되 큐가 비거나 응답이 올 때까지 현재 스레드를 막는다
+#[cfg(feature = "blocking")]
+impl Muxer {
+    fn blocking_send_to(&self, tx: &flume::Sender<Queued>, cmd: Command) -> Result<()> {
+        let queued = self.enqueue(cmd)?;
+        tx.send(queued).map_err(|_| self.closed_error())
+    }
+
+    pub fn blocking_write(&self, data: impl Into<Vec<u8>>) -> Result<()> {
+        self.blocking_send_to(&self.tx, Command::Write { data: data.into() })
+    }
+
+    pub fn blocking_write_priority(&self, data: impl Into<Vec<u8>>) -> Result<()> {
+        self.blocking_send_to(&self.priority_tx, Command::Write { data: data.into() })
+    }
+
+    pub fn blocking_write_move(&self, x: i32, y: i32) -> Result<()> {
+        self.blocking_send_to(&self.tx, Command::Move { x, y })
+    }
+
+    pub fn blocking_write_session(
+        &self,
+        key: impl Into<String>,
+        data: impl Into<Vec<u8>>,
+        active: bool,
+    ) -> Result<()> {
+        let data = data.into();
+        if !active {
+            self.update_session(key.into(), None);
+            return self.blocking_write_priority(data);
+        }
+
+        self.blocking_write(data.clone())?;
+        self.update_session(key.into(), Some(data));
+        Ok(())
+    }
+
+    pub fn blocking_write_read(&self, data: impl Into<Vec<u8>>) -> Result<String> {
+        self.blocking_write_read_timeout(data, self.command_timeout)
+    }
+
+    pub fn blocking_write_read_timeout(
+        &self,
+        data: impl Into<Vec<u8>>,
+        timeout: Duration,
+    ) -> Result<String> {
+        let (tx, rx) = reply();
+        let data = data.into();
+        self.blocking_send_to(&self.tx, Command::WriteRead { data, tx })?;
+        self.response(rx.recv_timeout(timeout))
+    }
+
+    pub fn blocking_set_baud_rate(&self, baud_rate: u32) -> Result<()> {
+        let (tx, rx) = reply();
+        self.blocking_send_to(&self.tx, Command::SetBaudRate { baud_rate, tx })?;
+        rx.recv().map_err(|_| self.closed_error())??;
+        self.shared.baud_rate.store(baud_rate, Ordering::Release);
         Ok(())
     }
+
+    pub fn blocking_close(&self) -> Result<()> {
+        let (tx, rx) = reply();
+        self.priority_tx
+            .send(Queued::new(Command::Close { tx }))
+            .map_err(|_| self.closed_error())?;
+        _ = rx.recv();
+        Ok(())
+    }
+}
+
+struct ButtonPublisher {
+    watch_tx: watch::Sender<ButtonState>,
+    event_tx: broadcast::Sender<ButtonEvent>,
+    sequence: u64,
+    stats: Arc<Counters>,
+}
+
+impl ButtonPublisher {
+    fn publish(&mut self, state: ButtonState) {
+        let timestamp = Instant::now();
+        let previous = *self.watch_tx.borrow();
+
+        // 한 번에 읽힌 마스크들도 전이 하나하나를 이벤트로 남긴다
+        for (button, pressed) in state.diff(previous) {
+            // 가득 찬 채널에 보내면 가장 느린 구독자가 아직 못 본 이벤트가 밀려난다
+            let event = ButtonEvent {
+                button,
+                pressed,
+                timestamp,
+                sequence: self.sequence,
+            };
+            if self.event_tx.send(event).is_some() {
+                Counters::add(&self.stats.dropped_button_updates, 1);
+            }
+            self.sequence += 1;
+        }
+
+        self.watch_tx.send_replace(state);
+    }
+}
+
+// 응답을 기다리는 명령이 이보다 많이 쌓이면 응답 없는 쓰기부터 버린다
+const MAX_IN_FLIGHT: usize = 256;
+
+/// 장치가 아직 에코하지 않은 명령 한 줄
+struct InFlight {
+    line: String,
+    tx: Option<Reply<Result<String>>>,
+    enqueued: Instant,
+}
+
+enum Dispatched {
+    /// 마스크가 빠진 보고는 `None`
+    Buttons(Option<u8>),
+    /// 기다리던 호출자에게 응답을 넘겼다면 요청부터 걸린 시간
+    Resolved(Option<Duration>),
+}
+
+/// 쓰기 스레드가 채우고 읽기 스레드가 응답과 맞춰 비운다
+#[derive(Default)]
+struct InFlightQueue {
+    entries: VecDeque<InFlight>,
+    // 보레이트를 바꾼 뒤에는 읽다 만 입력을 버린다
+    discard_input: bool,
+    stats: Arc<Counters>,
+}
+
+impl InFlightQueue {
+    fn track(&mut self, data: &[u8], mut tx: Option<Reply<Result<String>>>, enqueued: Instant) {
+        if data.starts_with(&frame::MAGIC) {
+            return;
+        }
+        self.prune();
+
+        let lines = command_lines(data);
+        let count = lines.len();
+        for (i, line) in lines.into_iter().enumerate() {
+            self.entries.push_back(InFlight {
+                line: String::from_utf8_lossy(line).into_owned(),
+                // 응답은 마지막 명령에 대한 것만 돌려준다
+                tx: if i + 1 == count { tx.take() } else { None },
+                enqueued,
+            });
+        }
+
+        while self.entries.len() > MAX_IN_FLIGHT {
+            match self.entries.iter().position(|entry| entry.tx.is_none()) {
+                Some(index) => _ = self.entries.remove(index),
+                None => break,
+            }
+        }
+    }
+
+    // 시간 초과로 포기한 요청은 에코가 오지 않았을 수 있다. 남겨 두면 같은 명령의
+    // 다음 응답을 가로채 이후 요청이 하나씩 밀리므로 버린다
+    fn prune(&mut self) {
+        self.entries
+            .retain(|entry| !entry.tx.as_ref().is_some_and(|tx| tx.is_disconnected()));
+    }
+
+    /// 응답을 기다리던 호출자가 있었다면 요청부터 응답까지 걸린 시간을 돌려준다
+    fn resolve(&mut self, line: &str, value: Option<&str>) -> Option<Duration> {
+        self.prune();
+        let Some(index) = self.entries.iter().position(|entry| entry.line == line) else {
+            tracing::debug!(line, "unsolicited response");
+            return None;
+        };
+
+        // 앞선 명령의 에코가 빠졌다면 그 명령을 기다리던 호출자에게 알린다
+        for skipped in self.entries.drain(..index) {
+            if let Some(tx) = skipped.tx {
+                _ = tx.send(Err(Error::UnexpectedResponse {
+                    expected: skipped.line,
+                    got: line.to_owned(),
+                }));
+            }
+        }
+
+        let entry = self.entries.pop_front().expect("matched entry");
+        let tx = entry.tx?;
+        // 응답을 받은 호출자가 바로 통계를 읽어도 이 응답이 들어 있도록 먼저 기록한다
+        let latency = entry.enqueued.elapsed();
+        self.stats.record_response(latency);
+        _ = tx.send(Ok(value.unwrap_or_default().to_owned()));
+        Some(latency)
+    }
+
+    /// 버튼 보고는 기다리는 요청과 맞추지 않고 그대로 돌려준다
+    fn dispatch(&mut self, response: &str) -> Dispatched {
+        // km.buttons()\n<mask>
+        if let Some(mask) = response.strip_prefix("km.buttons()\n") {
+            return Dispatched::Buttons(mask.as_bytes().first().copied());
+        }
+
+        let (line, value) = split_response(response);
+        Dispatched::Resolved(self.resolve(line, value))
+    }
+
+    fn fail(&mut self, error: impl Fn() -> Error) {
+        for entry in self.entries.drain(..) {
+            if let Some(tx) = entry.tx {
+                _ = tx.send(Err(error()));
+            }
+        }
+    }
+}
+
+/// 장치에서 오는 바이트를 기다렸다가 응답과 버튼 보고를 나눠 준다
+struct Reader {
+    com: Box<dyn Transport>,
+    buttons: ButtonPublisher,
+    in_flight: Arc<Mutex<InFlightQueue>>,
+    stop: Arc<AtomicBool>,
+    failed_tx: flume::Sender<std::io::Error>,
+    stats: Arc<Counters>,
+}
+
+impl Reader {
+    fn run(mut self) -> ButtonPublisher {
+        let mut responses = ResponseBuffer::default();
+        let mut buf = [0u8; 1024];
+
+        // 읽기 시간 제한은 종료 요청을 확인하는 주기일 뿐이다
+        while !self.stop.load(Ordering::Acquire) {
+            let n = match self.com.read(&mut buf) {
+                Ok(0) => {
+                    _ = self.failed_tx.send(ErrorKind::UnexpectedEof.into());
+                    break;
+                }
+                Ok(n) => n,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::TimedOut | ErrorKind::WouldBlock | ErrorKind::Interrupted
+                    ) =>
+                {
+                    continue;
+                }
+                Err(e) => {
+                    _ = self.failed_tx.send(e);
+                    break;
+                }
+            };
+
+            Counters::add(&self.stats.bytes_read, n as u64);
+            if std::mem::take(&mut self.in_flight.lock().unwrap().discard_input) {
+                responses.clear();
+            }
+            responses.extend(&buf[..n]);
+            while let Some(response) = responses.next_response() {
+                tracing::debug!("serial_read: {response}");
+                self.dispatch(&response);
+            }
+        }
+
+        self.buttons
+    }
+
+    fn dispatch(&mut self, response: &str) {
+        let dispatched = self.in_flight.lock().unwrap().dispatch(response);
+        match dispatched {
+            Dispatched::Buttons(Some(button)) => {
+                tracing::debug!("buttons: {}", button);
+                self.buttons.publish(ButtonState::from_bits(button));
+            }
+            Dispatched::Buttons(None) => Counters::add(&self.stats.dropped_button_updates, 1),
+            Dispatched::Resolved(latency) => tracing::trace!(?latency, "response"),
+        }
+    }
+}
+
+struct ReaderHandle {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<ButtonPublisher>,
+}
+
+enum Wake {
+    Command(Queued),
+    Priority(Queued),
+    ReaderFailed(std::io::Error),
+    Closed,
+}
+
+/// 명령 큐를 기다렸다가 바로 쓰고, 연결이 끊기면 재연결을 맡는다
+struct Worker {
+    com: Box<dyn Transport>,
+    rx: flume::Receiver<Queued>,
+    priority_rx: flume::Receiver<Queued>,
+    // 우선 명령에 자리를 내준 이동. 큐보다 먼저 보낸다
+    backlog: VecDeque<Queued>,
+    // 재연결을 기다리는 동안 꺼낸 우선 명령. 다시 연결되면 먼저 보낸다
+    held: VecDeque<Queued>,
+    failed_tx: flume::Sender<std::io::Error>,
+    failed_rx: flume::Receiver<std::io::Error>,
+    reader: Option<ReaderHandle>,
+    // 읽기 스레드가 멈춰 있는 동안 보관한다
+    buttons: Option<ButtonPublisher>,
+    in_flight: Arc<Mutex<InFlightQueue>>,
+    shared: Arc<Shared>,
+    reconnect: Option<Reconnect>,
+    close_tx: Option<Reply<()>>,
 }
 
 fn spawn_serial_worker(
-    mut com: Box<dyn SerialPort>,
-    mut rx: mpsc::Receiver<Command>,
-    watch_tx: watch::Sender<u8>,
-) {
+    com: Box<dyn Transport>,
+    rx: flume::Receiver<Queued>,
+    priority_rx: flume::Receiver<Queued>,
+    buttons: ButtonPublisher,
+    shared: Arc<Shared>,
+    reconnect: Option<Reconnect>,
+) -> Result<()> {
+    let (failed_tx, failed_rx) = flume::unbounded();
+    let mut worker = Worker {
+        com,
+        rx,
+        priority_rx,
+        backlog: VecDeque::new(),
+        held: VecDeque::new(),
+        failed_tx,
+        failed_rx,
+        reader: None,
+        buttons: Some(buttons),
+        in_flight: Arc::new(Mutex::new(InFlightQueue {
+            stats: Arc::clone(&shared.stats),
+            ..Default::default()
+        })),
+        shared,
+        reconnect,
+        close_tx: None,
+    };
+    worker.start_reader()?;
+
     std::thread::spawn(move || {
+        let reason = worker.run();
+        worker.stop_reader();
+
+        // 닫힌 큐를 본 호출자가 바로 이유를 알 수 있도록 먼저 알린다
+        worker
+            .shared
+            .connection
+            .publish(ConnectionEvent::Disconnected(reason.clone()));
+        if let DisconnectReason::Io(error) = reason {
+            tracing::error!("시리얼 워커 종료: {error}");
+            worker.reject_queued(|| Error::WorkerFailed(Arc::clone(&error)));
+        }
+
+        let close_tx = worker.close_tx.take();
+        drop(worker);
+        if let Some(tx) = close_tx {
+            _ = tx.send(());
+        }
+        tracing::debug!("Serial worker closed");
+    });
+    Ok(())
+}
+
+/// 한 번에 쓴 바이트를 장치가 하나씩 에코하는 명령 줄로 나눈다
+fn command_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split(|&b| b == b'\r' || b == b'\n')
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// 에코된 명령 줄과 그 뒤의 값으로 나눈다
+fn split_response(response: &str) -> (&str, Option<&str>) {
+    match response.split_once('\n') {
+        Some((line, value)) => (
+            line.trim_end_matches('\r'),
+            Some(value.trim_end_matches('\r')),
+        ),
+        None => (response.trim_end_matches('\r'), None),
+    }
+}
+
+impl Worker {
+    fn run(&mut self) -> DisconnectReason {
         loop {
-            match run_serial_loop(&mut com, &mut rx, &watch_tx) {
+            let result = match self.wait() {
+                Wake::Command(queued) => self.handle_command(queued),
+                Wake::Priority(cmd) => self.handle_priority(cmd),
+                Wake::ReaderFailed(e) => Err(e.into()),
+                Wake::Closed => Err(Error::ChannelClosed),
+            };
+
+            match result {
                 Ok(()) => continue,
                 Err(Error::IoTimeout) => continue,
+                Err(Error::ChannelClosed) => return DisconnectReason::Closed,
                 Err(e) => {
-                    tracing::debug!("run_serial_loop error: {e:?}");
-                    break;
+                    tracing::error!("시리얼 워커 오류: {e}");
+                    let error = Arc::new(e.into_io());
+                    self.stop_reader();
+                    self.shared
+                        .connection
+                        .lost(Arc::clone(&error), self.reconnect.is_some());
+                    self.in_flight
+                        .lock()
+                        .unwrap()
+                        .fail(|| Error::WorkerFailed(Arc::clone(&error)));
+                    if self.reconnect() {
+                        continue;
+                    }
+                    if self.close_tx.is_some() || self.rx.is_disconnected() {
+                        return DisconnectReason::Closed;
+                    }
+                    return DisconnectReason::Io(error);
                 }
             }
         }
+    }
 
-        drop(com);
-        tracing::debug!("Serial worker closed");
-    });
+    fn wait(&mut self) -> Wake {
+        if let Some(cmd) = self.held.pop_front() {
+            return Wake::Priority(cmd);
+        }
+        if let Ok(cmd) = self.priority_rx.try_recv() {
+            return Wake::Priority(cmd);
+        }
+        if let Some(cmd) = self.backlog.pop_front() {
+            return Wake::Command(cmd);
+        }
+
+        // 두 큐의 송신자는 함께 사라지므로 한쪽이 닫히면 남은 명령만 마저 꺼낸다
+        let (rx, priority_rx) = (&self.rx, &self.priority_rx);
+        flume::Selector::new()
+            .recv(priority_rx, |cmd| match cmd {
+                Ok(cmd) => Wake::Priority(cmd),
+                Err(_) => rx.try_recv().map_or(Wake::Closed, Wake::Command),
+            })
+            .recv(rx, |cmd| match cmd {
+                Ok(cmd) => Wake::Command(cmd),
+                Err(_) => priority_rx.try_recv().map_or(Wake::Closed, Wake::Priority),
+            })
+            .recv(&self.failed_rx, |e| {
+                Wake::ReaderFailed(e.expect("worker holds a sender"))
+            })
+            .wait()
+    }
+
+    fn next_queued(&mut self) -> Option<Queued> {
+        self.backlog.pop_front().or_else(|| self.rx.try_recv().ok())
+    }
+
+    fn handle_priority(&mut self, priority: Queued) -> Result<()> {
+        if matches!(priority.cmd, Command::Close { .. }) {
+            return self.handle_command(priority);
+        }
+
+        // 이동만 앞지른다. 누르기나 잠금을 앞지르면 해제가 먼저 도착해 상태가
+        // 뒤집히므로 큐에 남은 마지막 다른 명령까지는 순서대로 보낸다.
+        // 우선 명령보다 늦게 들어온 명령은 기다리지 않는다
+        let mut queued: VecDeque<Queued> = self.backlog.drain(..).collect();
+        queued.extend(self.rx.try_iter());
+        let later = queued
+            .iter()
+            .position(|queued| queued.enqueued > priority.enqueued)
+            .unwrap_or(queued.len());
+        let later = queued.split_off(later);
+        let barrier = queued
+            .iter()
+            .rposition(|queued| !matches!(queued.cmd, Command::Move { .. }))
+            .map_or(0, |index| index + 1);
+        self.backlog = queued.split_off(barrier);
+        self.backlog.extend(later);
+
+        for queued in queued {
+            match queued.cmd {
+                Command::Move { x, y } => self.write_move(x, y, &[queued.enqueued])?,
+                _ => self.handle_command(queued)?,
+            }
+        }
+        self.handle_command(priority)
+    }
+
+    fn write(
+        &mut self,
+        data: &[u8],
+        tx: Option<Reply<Result<String>>>,
+        enqueued: Instant,
+    ) -> Result<()> {
+        // 에코가 먼저 도착해도 맞출 수 있도록 쓰기 전에 등록한다
+        self.in_flight.lock().unwrap().track(data, tx, enqueued);
+
+        // 응답이 쓰기보다 먼저 호출자에게 닿을 수 있으므로 쓰기 전에 센다.
+        // 보레이트 변경 프레임은 명령 하나로 센다
+        let commands = if data.starts_with(&frame::MAGIC) {
+            1
+        } else {
+            command_lines(data).len()
+        };
+        let stats = Arc::clone(&self.shared.stats);
+        Counters::add(&stats.commands_sent, commands as u64);
+        Counters::add(&stats.bytes_written, data.len() as u64);
+        serial_write(self.com.as_mut(), data)?;
+        stats.record_write(enqueued.elapsed());
+        Ok(())
+    }
+
+    // 합쳐진 이동마다 큐에 들어간 시각부터 쓰기까지의 지연을 남긴다
+    fn write_move(&mut self, x: i32, y: i32, enqueued: &[Instant]) -> Result<()> {
+        let data = move_command(x, y).into_bytes();
+        if !data.is_empty() {
+            self.write(&data, None, enqueued[0])?;
+            for enqueued in &enqueued[1..] {
+                self.shared.stats.record_write(enqueued.elapsed());
+            }
+        }
+        Ok(())
+    }
+
+    fn start_reader(&mut self) -> Result<()> {
+        let com = self.com.try_clone()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let reader = Reader {
+            com,
+            buttons: self.buttons.take().expect("reader is stopped"),
+            in_flight: Arc::clone(&self.in_flight),
+            stop: Arc::clone(&stop),
+            failed_tx: self.failed_tx.clone(),
+            stats: Arc::clone(&self.shared.stats),
+        };
+        let thread = std::thread::spawn(move || reader.run());
+        self.reader = Some(ReaderHandle { stop, thread });
+        Ok(())
+    }
+
+    fn stop_reader(&mut self) {
+        let Some(reader) = self.reader.take() else {
+            return;
+        };
+        reader.stop.store(true, Ordering::Release);
+        self.buttons = Some(reader.thread.join().expect("reader thread panicked"));
+        // 멈춘 읽기 스레드가 남긴 오류는 이미 처리했다
+        self.failed_rx.drain();
+    }
+
+    fn handle_command(&mut self, queued: Queued) -> Result<()> {
+        let Queued { cmd, enqueued } = queued;
+        match cmd {
+            Command::Write { data } => self.write(&data, None, enqueued),
+            Command::Move { mut x, mut y } => {
+                // 큐에 쌓인 이동을 합치되 다른 명령은 순서를 지키는 경계로 남긴다
+                let mut merged = vec![enqueued];
+                let mut barrier = None;
+                if self.shared.coalesce_moves.load(Ordering::Relaxed) {
+                    while self.held.is_empty()
+                        && self.priority_rx.is_empty()
+                        && let Some(queued) = self.next_queued()
+                    {
+                        match queued.cmd {
+                            Command::Move { x: dx, y: dy } => {
+                                x = x.saturating_add(dx);
+                                y = y.saturating_add(dy);
+                                merged.push(queued.enqueued);
+                            }
+                            _ => {
+                                barrier = Some(queued);
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                Counters::add(&self.shared.stats.moves_coalesced, merged.len() as u64 - 1);
+                self.write_move(x, y, &merged)?;
+                match barrier {
+                    Some(queued) => self.handle_command(queued),
+                    None => Ok(()),
+                }
+            }
+            Command::WriteRead { data, tx } => self.write(&data, Some(tx), enqueued),
+            Command::SetBaudRate { baud_rate, tx } => {
+                tracing::debug!(baud_rate, "Command::SetBaudRate");
+                let result = self
+                    .com
+                    .flush()
+                    .and_then(|()| self.com.set_baud_rate(baud_rate))
+                    .map_err(Error::from);
+                // 이전 보레이트로 보낸 명령의 응답은 오지 않는다
+                let mut in_flight = self.in_flight.lock().unwrap();
+                in_flight.fail(|| Error::BaudRateChanged);
+                in_flight.discard_input = true;
+                drop(in_flight);
+                _ = tx.send(result);
+                Ok(())
+            }
+            Command::Close { tx } => {
+                tracing::debug!("Command::Close");
+                self.close_tx = Some(tx);
+                Err(Error::ChannelClosed)
+            }
+        }
+    }
+
+    fn reconnect(&mut self) -> bool {
+        let Some(mut reconnect) = self.reconnect.take() else {
+            return false;
+        };
+        tracing::warn!("장치 연결 끊김, 재연결 시도");
+
+        let mut attempt = 0;
+        let reconnected = loop {
+            if reconnect
+                .policy
+                .max_attempts
+                .is_some_and(|max_attempts| attempt >= max_attempts)
+                || self.interrupted(Duration::ZERO)
+            {
+                break false;
+            }
+            self.shared
+                .connection
+                .publish(ConnectionEvent::ReconnectAttempt {
+                    attempt: attempt + 1,
+                });
+            if self.interrupted(reconnect.policy.backoff(attempt)) {
+                break false;
+            }
+            attempt += 1;
+
+            match (reconnect.connector)() {
+                Ok(com) => match self.restore(com) {
+                    Ok(()) => break true,
+                    Err(e) => tracing::debug!(attempt, "restore failed: {e:?}"),
+                },
+                Err(e) => tracing::debug!(attempt, "reconnect failed: {e:?}"),
+            }
+        };
+
+        self.reconnect = Some(reconnect);
+        if reconnected {
+            tracing::info!(attempt, "장치 재연결됨");
+            Counters::add(&self.shared.stats.reconnects, 1);
+            self.shared
+                .connection
+                .publish(ConnectionEvent::Reconnected { attempts: attempt });
+        }
+        reconnected
+    }
+
+    // 다시 연결된 장치에 보레이트와 세션 상태를 다시 적용하고 읽기를 재개한다
+    fn restore(&mut self, mut com: Box<dyn Transport>) -> Result<()> {
+        let baud_rate = self.shared.baud_rate.load(Ordering::Acquire);
+
+        if handshake(com.as_mut())? {
+            if baud_rate != DEFAULT_BAUD_RATE {
+                serial_write(com.as_mut(), &Frame::SetBaudRate(baud_rate).encode())?;
+                com.flush()?;
+                com.set_baud_rate(baud_rate)?;
+                if !handshake(com.as_mut())? {
+                    return Err(Error::ResponseTimeout);
+                }
+            }
+        } else {
+            // 전원이 유지됐다면 장치는 이전 보레이트에 머물러 있다
+            if baud_rate == DEFAULT_BAUD_RATE {
+                return Err(Error::ResponseTimeout);
+            }
+            com.set_baud_rate(baud_rate)?;
+            if !handshake(com.as_mut())? {
+                return Err(Error::ResponseTimeout);
+            }
+        }
+
+        let session = self.shared.session.lock().unwrap().clone();
+        for data in session.values() {
+            serial_write(com.as_mut(), data)?;
+        }
+
+        self.com = com;
+        self.start_reader()
+    }
+
+    // 닫기 요청이 오거나 모든 핸들이 사라질 때까지 최대 `timeout` 동안 기다린다.
+    // 그 사이 꺼낸 우선 명령은 `held` 에 모아 두고, 거절할 때는 큐에 남은 명령도 꺼낸다
+    fn interrupted(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let reject = self.shared.while_disconnected == WhileDisconnected::Reject;
+        while self.close_tx.is_none() {
+            // 큐가 꽉 찬 것처럼 보내는 쪽을 기다리게 한다
+            if self.held.len() >= COMMAND_QUEUE_CAPACITY {
+                std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                return false;
+            }
+            let mut selector = flume::Selector::new().recv(&self.priority_rx, |queued| queued);
+            if reject {
+                selector = selector.recv(&self.rx, |queued| queued);
+            }
+            match selector.wait_deadline(deadline) {
+                Ok(Ok(Queued {
+                    cmd: Command::Close { tx },
+                    ..
+                })) => self.close_tx = Some(tx),
+                Ok(Ok(queued)) if reject => {
+                    self.reject(queued.cmd, || Error::DeviceDisconnected);
+                }
+                Ok(Ok(queued)) => self.held.push_back(queued),
+                Ok(Err(flume::RecvError::Disconnected)) => return true,
+                Err(flume::select::SelectError::Timeout) => return false,
+            }
+        }
+        true
+    }
+
+    fn reject_queued(&mut self, error: impl Fn() -> Error) {
+        let queued: Vec<Queued> = self
+            .held
+            .drain(..)
+            .chain(self.priority_rx.try_iter())
+            .chain(self.backlog.drain(..))
+            .chain(self.rx.try_iter())
+            .collect();
+        for queued in queued {
+            self.reject(queued.cmd, &error);
+        }
+    }
+
+    fn reject(&mut self, cmd: Command, error: impl Fn() -> Error) {
+        match cmd {
+            Command::Write { .. } | Command::Move { .. } => {}
+            Command::WriteRead { tx, .. } => _ = tx.send(Err(error())),
+            Command::SetBaudRate { tx, .. } => _ = tx.send(Err(error())),
+            Command::Close { tx } => self.close_tx = Some(tx),
+        }
+    }
 }
 
-fn poll_buttons(
-    com: &mut Box<dyn serialport::SerialPort>,
-    watch_tx: &watch::Sender<u8>,
-) -> Result<()> {
-    let responses = serial_read(com)?;
-    for response in responses {
-        let bytes = response.as_bytes();
-        tracing::debug!("serial_read: {response}");
-        // km.buttons()\n<mask>
-        let prefix = b"km.buttons()\n";
-        if bytes.starts_with(prefix) {
-            if let Some(&button) = bytes.get(prefix.len()) {
-                tracing::debug!("buttons: {}", button);
-                _ = watch_tx.send(button);
+fn handshake(com: &mut dyn Transport) -> Result<bool> {
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    while Instant::now() < deadline {
+        serial_write(com, b"km.version()\r")?;
+
+        let retry_at = Instant::now() + HANDSHAKE_INTERVAL;
+        while Instant::now() < retry_at {
+            let responses = serial_read(com)?;
+            let answered = responses.iter().any(|response| {
+                let (line, value) = split_response(response);
+                line == "km.version()" && value.is_some_and(check_version)
+            });
+            if answered {
+                return Ok(true);
             }
         }
     }
-    Ok(())
+    Ok(false)
 }
 
-fn handle_command(com: &mut Box<dyn serialport::SerialPort>, cmd: Command) -> Result<()> {
-    match cmd {
-        Command::Write { data } => serial_write(com, &data),
-        Command::WriteRead { data, tx } => {
-            serial_write(com, &data)?;
-            let read_results = serial_read(com)?;
-            let read_result = read_results.into_iter().next().unwrap_or_default();
-            tracing::debug!("Read data: {read_result}");
-            _ = tx.send(read_result);
-            Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MouseButton;
+
+    fn publisher() -> ButtonPublisher {
+        let (watch_tx, _) = watch::channel(ButtonState::default());
+        let event_tx = broadcast::channel(BUTTON_EVENT_CAPACITY);
+        ButtonPublisher {
+            watch_tx,
+            event_tx,
+            sequence: 0,
+            stats: Arc::default(),
         }
-        Command::Close => {
-            tracing::debug!("Command::Close");
-            Err(Error::ChannelClosed)
+    }
+
+    #[test]
+    fn press_and_release_in_one_batch() {
+        let mut buttons = publisher();
+        let mut events = buttons.event_tx.subscribe();
+        let mut state = buttons.watch_tx.subscribe();
+
+        // 한 번에 읽힌 두 보고
+        buttons.publish(ButtonState::from_bits(1));
+        buttons.publish(ButtonState::default());
+
+        let press = events.try_recv().unwrap();
+        let release = events.try_recv().unwrap();
+        assert_eq!((press.button, press.pressed), (MouseButton::Left, true));
+        assert_eq!(
+            (release.button, release.pressed),
+            (MouseButton::Left, false)
+        );
+        assert_eq!(release.sequence, press.sequence + 1);
+        assert_eq!(*state.borrow_and_update(), ButtonState::default());
+    }
+
+    #[test]
+    fn lagged_subscriber_sees_gap() {
+        let mut buttons = publisher();
+        let mut events = buttons.event_tx.subscribe();
+
+        for i in 0..BUTTON_EVENT_CAPACITY + 10 {
+            buttons.publish(ButtonState::from_bits(u8::from(i % 2 == 0)));
         }
+
+        let Err(broadcast::TryRecvError::Lagged(skipped)) = events.try_recv() else {
+            panic!("subscriber should lag");
+        };
+        assert_eq!(skipped, 10);
+        assert_eq!(events.try_recv().unwrap().sequence, skipped);
+        assert_eq!(buttons.stats.snapshot(0).dropped_button_updates, skipped);
     }
-}
 
-fn run_serial_loop(
-    com: &mut Box<dyn serialport::SerialPort>,
-    rx: &mut mpsc::Receiver<Command>,
-    watch_tx: &watch::Sender<u8>,
-) -> Result<()> {
-    match rx.try_recv() {
-        Ok(cmd) => handle_command(com, cmd),
-        Err(mpsc::error::TryRecvError::Empty) => poll_buttons(com, watch_tx),
-        Err(mpsc::error::TryRecvError::Disconnected) => Err(Error::ChannelClosed),
+    fn request(queue: &mut InFlightQueue, data: &str) -> flume::Receiver<Result<String>> {
+        let (tx, rx) = reply();
+        queue.track(data.as_bytes(), Some(tx), Instant::now());
+        rx
+    }
+
+    #[test]
+    fn button_report_before_echo() {
+        let mut queue = InFlightQueue::default();
+        let rx = request(&mut queue, "km.version()\r");
+
+        assert!(matches!(
+            queue.dispatch("km.buttons()\n\x01"),
+            Dispatched::Buttons(Some(1))
+        ));
+        assert!(rx.try_recv().is_err());
+
+        assert!(matches!(
+            queue.dispatch("km.version()\r\nkm.MAKCU"),
+            Dispatched::Resolved(Some(_))
+        ));
+        assert_eq!(rx.try_recv().unwrap().unwrap(), "km.MAKCU");
+        assert!(queue.entries.is_empty());
+    }
+
+    #[test]
+    fn reply_after_timeout_is_dropped() {
+        let mut queue = InFlightQueue::default();
+        drop(request(&mut queue, "km.left()\r"));
+        let rx = request(&mut queue, "km.version()\r");
+
+        assert!(matches!(
+            queue.dispatch("km.left()\r\n1"),
+            Dispatched::Resolved(None)
+        ));
+        assert!(rx.try_recv().is_err());
+
+        queue.dispatch("km.version()\r\nkm.MAKCU");
+        assert_eq!(rx.try_recv().unwrap().unwrap(), "km.MAKCU");
+    }
+
+    #[test]
+    fn missing_echo_fails_skipped_request() {
+        let mut queue = InFlightQueue::default();
+        let skipped = request(&mut queue, "km.left()\r");
+        let rx = request(&mut queue, "km.version()\r");
+
+        queue.dispatch("km.version()\r\nkm.MAKCU");
+        match skipped.try_recv().unwrap() {
+            Err(Error::UnexpectedResponse { expected, got }) => {
+                assert_eq!(expected, "km.left()");
+                assert_eq!(got, "km.version()");
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+        assert_eq!(rx.try_recv().unwrap().unwrap(), "km.MAKCU");
+    }
+
+    #[test]
+    fn identical_requests_resolve_in_order() {
+        let mut queue = InFlightQueue::default();
+        let first = request(&mut queue, "km.left()\r");
+        let second = request(&mut queue, "km.left()\r");
+
+        queue.dispatch("km.left()\r\n0");
+        assert_eq!(first.try_recv().unwrap().unwrap(), "0");
+        assert!(second.try_recv().is_err());
+
+        queue.dispatch("km.left()\r\n1");
+        assert_eq!(second.try_recv().unwrap().unwrap(), "1");
+    }
+
+    #[test]
+    fn abandoned_request_without_echo_is_not_matched() {
+        let mut queue = InFlightQueue::default();
+        // 장치가 삼킨 요청. 호출자는 시간 초과로 포기했다
+        drop(request(&mut queue, "km.left()\r"));
+
+        for value in ["0", "1"] {
+            let rx = request(&mut queue, "km.left()\r");
+            queue.dispatch(&format!("km.left()\r\n{value}"));
+            assert_eq!(rx.try_recv().unwrap().unwrap(), value);
+        }
+        assert!(queue.entries.is_empty());
+    }
+
+    #[test]
+    fn baud_rate_change_fails_waiters() {
+        let mut queue = InFlightQueue::default();
+        queue.track(b"km.left(1)\r", None, Instant::now());
+        let rx = request(&mut queue, "km.version()\r");
+
+        queue.fail(|| Error::BaudRateChanged);
+        assert!(matches!(
+            rx.try_recv().unwrap(),
+            Err(Error::BaudRateChanged)
+        ));
+        assert!(queue.entries.is_empty());
     }
 }
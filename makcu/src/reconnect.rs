@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use crate::transport::Transport;
+
+/// 재연결 중에 들어온 명령을 어떻게 처리할지
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WhileDisconnected {
+    /// 명령 큐에 쌓아 두었다가 다시 연결되면 보낸다
+    #[default]
+    Queue,
+    /// 즉시 `DeviceDisconnected` 오류를 돌려준다
+    Reject,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// `None` 이면 장치가 다시 연결될 때까지 계속 시도한다
+    pub max_attempts: Option<u32>,
+    pub while_disconnected: WhileDisconnected,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            max_attempts: None,
+            while_disconnected: WhileDisconnected::default(),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// 장치를 다시 찾아 기본 보레이트로 연다
+pub(crate) type Connector = Box<dyn FnMut() -> crate::Result<Box<dyn Transport>> + Send>;
+
+pub(crate) struct Reconnect {
+    pub policy: ReconnectPolicy,
+    pub connector: Connector,
+}
@@ -0,0 +1,54 @@
+//! 비동기 API 가 쓰는 타이머. 실제 입출력은 워커 스레드가 하므로 실행기에 바라는 것은 이것뿐이다
+
+use std::{
+    future::{Future, poll_fn},
+    pin::pin,
+    task::Poll,
+    time::Duration,
+};
+
+#[cfg(not(any(feature = "rt-tokio", feature = "rt-smol")))]
+compile_error!("the async API needs a timer: enable `rt-tokio` or `rt-smol`");
+
+// async-io 타이머는 자체 드라이버 스레드가 있어 tokio 런타임 안에서도 동작한다
+#[cfg(feature = "rt-smol")]
+pub async fn sleep(duration: Duration) {
+    async_io::Timer::after(duration).await;
+}
+
+#[cfg(all(feature = "rt-tokio", not(feature = "rt-smol")))]
+pub async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await;
+}
+
+/// 시간 안에 끝나지 않으면 `None`
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+    let mut future = pin!(future);
+    let mut sleep = pin!(sleep(duration));
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        sleep.as_mut().poll(cx).map(|()| None)
+    })
+    .await
+}
+
+#[cfg(all(test, feature = "rt-smol"))]
+mod tests {
+    use std::future::pending;
+
+    use super::*;
+
+    #[test]
+    fn timeout_returns_output_before_deadline() {
+        let output = async_io::block_on(timeout(Duration::from_secs(1), async { 7 }));
+        assert_eq!(output, Some(7));
+    }
+
+    #[test]
+    fn timeout_expires() {
+        let output = async_io::block_on(timeout(Duration::from_millis(20), pending::<()>()));
+        assert_eq!(output, None);
+    }
+}
@@ -1,23 +1,24 @@
-use serialport::SerialPort;
+use std::io::ErrorKind;
 
-use crate::muxer::Result;
+use crate::{muxer::Result, transport::Transport};
 
-pub fn serial_write(com: &mut Box<dyn SerialPort>, data: &[u8]) -> Result<()> {
+const SUFFIX: &str = "\r\n>>> ";
+const MAX_BUFFER_SIZE: usize = 4096;
+
+pub fn serial_write(com: &mut dyn Transport, data: &[u8]) -> Result<()> {
     com.write_all(data)?;
     Ok(())
 }
 
-pub fn serial_read(com: &mut Box<dyn SerialPort>) -> Result<Vec<String>> {
-    const SUFFIX: &str = "\r\n>>> ";
-    const MAX_BUFFER_SIZE: usize = 4096;
-
+pub fn serial_read(com: &mut dyn Transport) -> Result<Vec<String>> {
     let mut buf = Vec::new();
     let mut temp_buf = [0u8; 1024];
 
     while !buf.ends_with(SUFFIX.as_bytes()) {
         let n = match com.read(&mut temp_buf) {
+            Ok(0) => return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into()),
             Ok(n) => n,
-            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => break,
+            Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => break,
             Err(e) => return Err(e.into()),
         };
 
@@ -36,3 +37,41 @@ pub fn serial_read(com: &mut Box<dyn SerialPort>) -> Result<Vec<String>> {
         .map(|s| s.trim_end_matches(SUFFIX).to_owned())
         .collect())
 }
+
+/// 여러 번에 걸쳐 읽힌 바이트를 프롬프트 단위의 응답으로 나눈다
+#[derive(Default)]
+pub struct ResponseBuffer {
+    buf: Vec<u8>,
+}
+
+impl ResponseBuffer {
+    pub fn extend(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    pub fn next_response(&mut self) -> Option<String> {
+        loop {
+            let Some(end) = self
+                .buf
+                .windows(SUFFIX.len())
+                .position(|window| window == SUFFIX.as_bytes())
+            else {
+                if self.buf.len() > MAX_BUFFER_SIZE {
+                    tracing::debug!(len = self.buf.len(), "프롬프트 없는 입력 버림");
+                    self.buf.clear();
+                }
+                return None;
+            };
+
+            let response = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+            self.buf.drain(..end + SUFFIX.len());
+            if !response.is_empty() {
+                return Some(response);
+            }
+        }
+    }
+}
@@ -0,0 +1,851 @@
+//! 실제 장치 없이 `km.*` 프로토콜을 흉내 내는 시뮬레이터
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{
+    ButtonState, DEFAULT_BAUD_RATE, LockTarget, MouseButton, Transport,
+    frame::{self, Frame, FrameError},
+};
+
+const PROMPT: &[u8] = b"\r\n>>> ";
+
+struct Device {
+    baud_rate: u32,
+    host_baud_rate: u32,
+    input: Vec<u8>,
+    output: VecDeque<u8>,
+    physical: ButtonState,
+    software: ButtonState,
+    locks: Vec<LockTarget>,
+    buttons_enabled: bool,
+    position: (i32, i32),
+    wheel: i32,
+    ignore_baud_frames: bool,
+    commands: Vec<String>,
+    // 참이면 호스트의 쓰기가 막힌다
+    stalled: bool,
+    // 버튼별로 눌렀다 뗀 횟수
+    clicks: [u32; MouseButton::ALL.len()],
+    // 다시 꽂을 때마다 증가해 이전 연결의 트랜스포트를 끊는다
+    generation: u64,
+    plugged: bool,
+}
+
+impl Device {
+    fn new() -> Self {
+        Self {
+            baud_rate: DEFAULT_BAUD_RATE,
+            host_baud_rate: DEFAULT_BAUD_RATE,
+            input: Vec::new(),
+            output: VecDeque::new(),
+            physical: ButtonState::default(),
+            software: ButtonState::default(),
+            locks: Vec::new(),
+            buttons_enabled: false,
+            position: (0, 0),
+            wheel: 0,
+            ignore_baud_frames: false,
+            commands: Vec::new(),
+            stalled: false,
+            clicks: [0; MouseButton::ALL.len()],
+            generation: 0,
+            plugged: true,
+        }
+    }
+
+    // 전원이 다시 들어온 장치처럼 설정을 초기화한다
+    fn power_cycle(&mut self) {
+        self.baud_rate = DEFAULT_BAUD_RATE;
+        self.host_baud_rate = DEFAULT_BAUD_RATE;
+        self.input.clear();
+        self.output.clear();
+        self.software = ButtonState::default();
+        self.locks.clear();
+        self.buttons_enabled = false;
+        self.generation += 1;
+    }
+
+    fn is_locked(&self, target: LockTarget) -> bool {
+        self.locks.contains(&target)
+    }
+
+    fn set_lock(&mut self, target: LockTarget, locked: bool) {
+        self.locks.retain(|&t| t != target);
+        if locked {
+            self.locks.push(target);
+        }
+    }
+
+    fn host_buttons(&self) -> ButtonState {
+        let physical = self
+            .physical
+            .pressed()
+            .filter(|&button| !self.is_locked(button.into()))
+            .fold(0, |bits, button| bits | button.mask());
+        ButtonState::from_bits(physical | self.software.bits())
+    }
+
+    fn send(&mut self, message: &[u8]) {
+        // 보레이트가 맞지 않으면 호스트는 알아볼 수 없는 바이트만 받는다
+        if self.baud_rate != self.host_baud_rate {
+            return;
+        }
+        self.output.extend(message);
+        self.output.extend(PROMPT);
+    }
+
+    fn set_physical(&mut self, state: ButtonState) {
+        if self.physical == state {
+            return;
+        }
+        self.physical = state;
+        if self.buttons_enabled {
+            let mut report = b"km.buttons()\n".to_vec();
+            report.push(state.bits());
+            self.send(&report);
+        }
+    }
+
+    fn receive(&mut self, data: &[u8]) {
+        if self.baud_rate != self.host_baud_rate {
+            return;
+        }
+        self.input.extend_from_slice(data);
+
+        loop {
+            if self.input.starts_with(&frame::MAGIC) {
+                match Frame::decode(&self.input) {
+                    Ok((frame, len)) => {
+                        self.input.drain(..len);
+                        self.handle_frame(frame);
+                    }
+                    Err(FrameError::Incomplete) => return,
+                    Err(e) => {
+                        tracing::debug!("sim: invalid frame: {e}");
+                        self.input.clear();
+                    }
+                }
+                continue;
+            }
+
+            let Some(end) = self.input.iter().position(|&b| b == b'\r' || b == b'\n') else {
+                return;
+            };
+            let line: Vec<u8> = self.input.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line[..end]).trim().to_owned();
+            if !line.is_empty() {
+                self.handle_line(&line);
+            }
+        }
+    }
+
+    fn handle_frame(&mut self, frame: Frame) {
+        match frame {
+            Frame::SetBaudRate(baud_rate) if self.ignore_baud_frames => {
+                tracing::debug!(baud_rate, "sim: baud frame ignored");
+            }
+            Frame::SetBaudRate(baud_rate) => {
+                self.baud_rate = baud_rate;
+                self.input.clear();
+            }
+        }
+    }
+
+    fn handle_line(&mut self, line: &str) {
+        self.commands.push(line.to_owned());
+        let value = self.execute(line);
+        let mut message = line.as_bytes().to_vec();
+        if let Some(value) = value {
+            message.extend_from_slice(b"\r\n");
+            message.extend_from_slice(value.as_bytes());
+        }
+        self.send(&message);
+    }
+
+    fn execute(&mut self, line: &str) -> Option<String> {
+        let (name, args) = line
+            .strip_prefix("km.")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .and_then(|rest| rest.split_once('('))?;
+        let args: Vec<i32> = args
+            .split(',')
+            .filter_map(|arg| arg.trim().parse().ok())
+            .collect();
+
+        if name == "version" {
+            return Some("km.MAKCU".to_owned());
+        }
+        if name == "move" {
+            if let [x, y] = args[..] {
+                self.position.0 += x;
+                self.position.1 += y;
+            }
+            return None;
+        }
+        if name == "wheel" {
+            if let [delta] = args[..] {
+                self.wheel += delta;
+            }
+            return None;
+        }
+        if name == "click" {
+            // 펌웨어가 간격을 재므로 여기서는 횟수만 센다
+            if let [index, count, ..] = args[..]
+                && let Some(clicks) = self.clicks.get_mut(index as usize)
+            {
+                *clicks += count.max(0) as u32;
+            }
+            return None;
+        }
+        if name == "buttons" {
+            return match args[..] {
+                [enabled] => {
+                    self.buttons_enabled = enabled != 0;
+                    None
+                }
+                _ => Some(self.physical.bits().to_string()),
+            };
+        }
+        if let Some(button) = MouseButton::ALL
+            .into_iter()
+            .find(|button| button.command_name() == name)
+        {
+            let mask = button.mask();
+            return match args[..] {
+                [pressed] => {
+                    if pressed == 0 && self.software.is_pressed(button) {
+                        self.clicks[button.index() as usize] += 1;
+                    }
+                    let bits = self.software.bits() & !mask;
+                    let bits = if pressed != 0 { bits | mask } else { bits };
+                    self.software = ButtonState::from_bits(bits);
+                    None
+                }
+                _ => Some(u8::from(self.software.is_pressed(button)).to_string()),
+            };
+        }
+        if let Some(target) = LockTarget::ALL
+            .into_iter()
+            .find(|target| target.command_name() == name)
+        {
+            return match args[..] {
+                [locked] => {
+                    self.set_lock(target, locked != 0);
+                    None
+                }
+                _ => Some(u8::from(self.is_locked(target)).to_string()),
+            };
+        }
+        None
+    }
+}
+
+struct Shared {
+    device: Mutex<Device>,
+    ready: Condvar,
+}
+
+/// 시뮬레이터 장치. 복제본은 같은 장치를 가리킨다
+#[derive(Clone)]
+pub struct Simulator {
+    shared: Arc<Shared>,
+}
+
+impl Simulator {
+    /// 장치와 그 장치에 연결된 호스트 쪽 트랜스포트를 만든다
+    pub fn new() -> (Simulator, SimTransport) {
+        let shared = Arc::new(Shared {
+            device: Mutex::new(Device::new()),
+            ready: Condvar::new(),
+        });
+        let simulator = Simulator { shared };
+        let transport = simulator.transport(0);
+        (simulator, transport)
+    }
+
+    fn transport(&self, generation: u64) -> SimTransport {
+        SimTransport {
+            shared: Arc::clone(&self.shared),
+            generation,
+            timeout: None,
+        }
+    }
+
+    /// USB 케이블을 뽑은 것처럼 기존 트랜스포트를 모두 끊는다
+    pub fn unplug(&self) {
+        self.with_device(|device| device.plugged = false);
+    }
+
+    /// 장치를 다시 꽂는다. 장치 설정은 초기화되고 새 트랜스포트로만 통신할 수 있다
+    pub fn plug(&self) -> SimTransport {
+        let generation = self.with_device(|device| {
+            device.power_cycle();
+            device.plugged = true;
+            device.generation
+        });
+        self.transport(generation)
+    }
+
+    fn with_device<T>(&self, f: impl FnOnce(&mut Device) -> T) -> T {
+        let mut device = self.shared.device.lock().unwrap();
+        let result = f(&mut device);
+        self.shared.ready.notify_all();
+        result
+    }
+
+    /// 사용자가 실제 버튼을 누른 것처럼 처리한다
+    pub fn press(&self, button: MouseButton) {
+        self.with_device(|device| {
+            let bits = device.physical.bits() | button.mask();
+            device.set_physical(ButtonState::from_bits(bits));
+        });
+    }
+
+    pub fn release(&self, button: MouseButton) {
+        self.with_device(|device| {
+            let bits = device.physical.bits() & !button.mask();
+            device.set_physical(ButtonState::from_bits(bits));
+        });
+    }
+
+    /// 누르고 떼는 보고 두 개를 호스트가 한 번에 읽도록 함께 보낸다
+    pub fn physical_click(&self, button: MouseButton) {
+        self.with_device(|device| {
+            let bits = device.physical.bits();
+            device.set_physical(ButtonState::from_bits(bits | button.mask()));
+            device.set_physical(ButtonState::from_bits(bits & !button.mask()));
+        });
+    }
+
+    /// 사용자가 실제 마우스를 움직인 것처럼 처리한다. 잠긴 축은 무시된다
+    pub fn physical_move(&self, x: i32, y: i32) {
+        self.with_device(|device| {
+            if !device.is_locked(LockTarget::X) {
+                device.position.0 += x;
+            }
+            if !device.is_locked(LockTarget::Y) {
+                device.position.1 += y;
+            }
+        });
+    }
+
+    /// 장치에 실제로 눌린 버튼
+    pub fn physical_buttons(&self) -> ButtonState {
+        self.with_device(|device| device.physical)
+    }
+
+    /// 호스트 PC 가 보는 버튼 상태
+    pub fn host_buttons(&self) -> ButtonState {
+        self.with_device(|device| device.host_buttons())
+    }
+
+    /// 호스트 명령으로 `button` 을 눌렀다 뗀 횟수
+    pub fn clicks(&self, button: MouseButton) -> u32 {
+        self.with_device(|device| device.clicks[button.index() as usize])
+    }
+
+    pub fn position(&self) -> (i32, i32) {
+        self.with_device(|device| device.position)
+    }
+
+    pub fn wheel(&self) -> i32 {
+        self.with_device(|device| device.wheel)
+    }
+
+    pub fn is_locked(&self, target: impl Into<LockTarget>) -> bool {
+        let target = target.into();
+        self.with_device(|device| device.is_locked(target))
+    }
+
+    pub fn buttons_enabled(&self) -> bool {
+        self.with_device(|device| device.buttons_enabled)
+    }
+
+    pub fn baud_rate(&self) -> u32 {
+        self.with_device(|device| device.baud_rate)
+    }
+
+    /// 보레이트 변경 프레임을 받아도 보레이트를 바꾸지 않는 펌웨어를 흉내 낸다
+    pub fn ignore_baud_frames(&self, ignore: bool) {
+        self.with_device(|device| device.ignore_baud_frames = ignore);
+    }
+
+    /// 장치가 받은 `km.*` 명령 줄. 받은 순서대로 돌려준다
+    pub fn commands(&self) -> Vec<String> {
+        self.with_device(|device| device.commands.clone())
+    }
+
+    /// 장치가 입력을 받지 않는 것처럼 [`Simulator::resume`] 까지 호스트의 쓰기를 막는다
+    pub fn stall(&self) {
+        self.with_device(|device| device.stalled = true);
+    }
+
+    pub fn resume(&self) {
+        self.with_device(|device| device.stalled = false);
+    }
+}
+
+/// 시뮬레이터에 연결된 호스트 쪽 트랜스포트
+pub struct SimTransport {
+    shared: Arc<Shared>,
+    generation: u64,
+    timeout: Option<Duration>,
+}
+
+impl SimTransport {
+    fn device(&self) -> io::Result<MutexGuard<'_, Device>> {
+        let device = self.shared.device.lock().unwrap();
+        if !device.plugged || device.generation != self.generation {
+            return Err(io::ErrorKind::NotConnected.into());
+        }
+        Ok(device)
+    }
+}
+
+impl Read for SimTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut device = self.device()?;
+        loop {
+            if !device.plugged || device.generation != self.generation {
+                return Err(io::ErrorKind::NotConnected.into());
+            }
+            if !device.output.is_empty() {
+                let n = buf.len().min(device.output.len());
+                for (dst, src) in buf.iter_mut().zip(device.output.drain(..n)) {
+                    *dst = src;
+                }
+                return Ok(n);
+            }
+
+            device = match self.timeout {
+                Some(timeout) => {
+                    let (device, result) = self.shared.ready.wait_timeout(device, timeout).unwrap();
+                    if result.timed_out() {
+                        return Err(io::ErrorKind::TimedOut.into());
+                    }
+                    device
+                }
+                None => self.shared.ready.wait(device).unwrap(),
+            };
+        }
+    }
+}
+
+impl Write for SimTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut device = self.device()?;
+        while device.stalled {
+            device = self.shared.ready.wait(device).unwrap();
+            if !device.plugged || device.generation != self.generation {
+                return Err(io::ErrorKind::NotConnected.into());
+            }
+        }
+        device.receive(buf);
+        drop(device);
+        self.shared.ready.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for SimTransport {
+    fn name(&self) -> Option<String> {
+        Some("sim".to_owned())
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = Some(timeout);
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(SimTransport {
+            shared: Arc::clone(&self.shared),
+            generation: self.generation,
+            timeout: self.timeout,
+        }))
+    }
+
+    // 장치와 보레이트가 다르면 통신이 되지 않는다
+    fn set_baud_rate(&mut self, baud_rate: u32) -> io::Result<()> {
+        let mut device = self.device()?;
+        device.host_baud_rate = baud_rate;
+        device.output.clear();
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use crate::{
+        ConnectionEvent, ConnectionState, DisconnectReason, Error, HIGH_SPEED_BAUD_RATE, Makcu,
+        ReconnectPolicy, WhileDisconnected, muxer,
+    };
+
+    const WAIT: Duration = Duration::from_secs(1);
+
+    fn connect() -> (Simulator, Makcu) {
+        let (sim, transport) = Simulator::new();
+        (sim, Makcu::from_transport(transport).unwrap())
+    }
+
+    #[tokio::test]
+    async fn version() {
+        let (_sim, makcu) = connect();
+        assert_eq!(makcu.version().await.unwrap(), "km.MAKCU");
+    }
+
+    #[tokio::test]
+    async fn physical_buttons_reach_subscribers() {
+        let (sim, makcu) = connect();
+        makcu.enable_buttons().await.unwrap();
+        // 버튼 보고가 켜진 뒤에 누르도록 왕복 한 번을 기다린다
+        makcu.version().await.unwrap();
+        let mut buttons = makcu.subscribe_buttons();
+
+        sim.press(MouseButton::Left);
+        tokio::time::timeout(WAIT, buttons.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(buttons.borrow_and_update().is_pressed(MouseButton::Left));
+
+        sim.release(MouseButton::Left);
+        tokio::time::timeout(WAIT, buttons.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(*buttons.borrow_and_update(), ButtonState::default());
+    }
+
+    #[tokio::test]
+    async fn click_in_one_batch_yields_two_events() {
+        let (sim, makcu) = connect();
+        makcu.enable_buttons().await.unwrap();
+        makcu.version().await.unwrap();
+        let mut events = makcu.subscribe_button_events();
+
+        sim.physical_click(MouseButton::Left);
+        let press = tokio::time::timeout(WAIT, events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let release = tokio::time::timeout(WAIT, events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((press.button, press.pressed), (MouseButton::Left, true));
+        assert_eq!(
+            (release.button, release.pressed),
+            (MouseButton::Left, false)
+        );
+        assert_eq!(release.sequence, press.sequence + 1);
+    }
+
+    #[tokio::test]
+    async fn lock_state() {
+        let (sim, makcu) = connect();
+        makcu.lock(LockTarget::X).await.unwrap();
+        makcu.lock(MouseButton::Right).await.unwrap();
+        assert!(makcu.is_locked(LockTarget::X).await.unwrap());
+        assert!(makcu.is_locked(MouseButton::Right).await.unwrap());
+        assert!(!makcu.is_locked(LockTarget::Y).await.unwrap());
+
+        makcu.unlock(LockTarget::X).await.unwrap();
+        assert!(!makcu.is_locked(LockTarget::X).await.unwrap());
+        assert!(sim.is_locked(MouseButton::Right));
+    }
+
+    #[tokio::test]
+    async fn baud_rate_frame() {
+        let (sim, mut transport) = Simulator::new();
+        transport
+            .write_all(&[0xDE, 0xAD, 0x05, 0x00, 0xA5, 0x00, 0x09, 0x3D, 0x00])
+            .unwrap();
+        assert_eq!(sim.baud_rate(), HIGH_SPEED_BAUD_RATE);
+
+        transport.set_baud_rate(HIGH_SPEED_BAUD_RATE).unwrap();
+        let makcu = Makcu::from_transport(transport).unwrap();
+        assert_eq!(makcu.version().await.unwrap(), "km.MAKCU");
+    }
+
+    #[tokio::test]
+    async fn baud_rate_switch() {
+        let (sim, makcu) = connect();
+        makcu.set_baud_rate(HIGH_SPEED_BAUD_RATE).await.unwrap();
+        assert_eq!(makcu.baud_rate(), HIGH_SPEED_BAUD_RATE);
+        assert_eq!(sim.baud_rate(), HIGH_SPEED_BAUD_RATE);
+    }
+
+    #[tokio::test]
+    async fn failed_baud_rate_switch_rolls_back() {
+        let (sim, makcu) = connect();
+        sim.ignore_baud_frames(true);
+
+        let result = makcu.set_baud_rate(HIGH_SPEED_BAUD_RATE).await;
+        assert!(matches!(
+            result,
+            Err(Error::BaudRateSwitchFailed {
+                from: DEFAULT_BAUD_RATE,
+                to: HIGH_SPEED_BAUD_RATE,
+            })
+        ));
+        assert_eq!(makcu.baud_rate(), DEFAULT_BAUD_RATE);
+        assert_eq!(sim.baud_rate(), DEFAULT_BAUD_RATE);
+        assert_eq!(makcu.version().await.unwrap(), "km.MAKCU");
+    }
+
+    #[tokio::test]
+    async fn stalled_device_times_out() {
+        let (sim, mut makcu) = connect();
+        makcu.set_command_timeout(Duration::from_millis(50));
+        sim.stall();
+
+        let result = makcu.version().await;
+        assert!(matches!(
+            result,
+            Err(Error::Muxer(crate::muxer::Error::ResponseTimeout))
+        ));
+
+        // 장치가 다시 입력을 받으면 이후 요청은 정상으로 돌아온다
+        sim.resume();
+        assert_eq!(makcu.version().await.unwrap(), "km.MAKCU");
+        assert_eq!(sim.commands(), ["km.version()", "km.version()"]);
+    }
+
+    // tokio 없이 async-io 만으로도 요청, 클릭 대기, 응답 시간 초과가 동작한다
+    #[cfg(feature = "rt-smol")]
+    #[test]
+    fn runs_on_async_io() {
+        let (sim, mut makcu) = connect();
+        async_io::block_on(async {
+            assert_eq!(makcu.version().await.unwrap(), "km.MAKCU");
+            makcu.click(MouseButton::Left).await.unwrap();
+
+            makcu.set_command_timeout(Duration::from_millis(50));
+            sim.stall();
+            assert!(matches!(
+                makcu.version().await,
+                Err(Error::Muxer(muxer::Error::ResponseTimeout))
+            ));
+        });
+    }
+
+    fn reject_while_disconnected(backoff: Duration) -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_backoff: backoff,
+            max_backoff: backoff,
+            while_disconnected: WhileDisconnected::Reject,
+            ..ReconnectPolicy::default()
+        }
+    }
+
+    // 워커가 연결이 끊긴 것을 알아챌 때까지 기다린다
+    async fn until_disconnected(makcu: &Makcu) {
+        tokio::time::timeout(WAIT, async {
+            while !matches!(
+                makcu.version().await,
+                Err(Error::Muxer(muxer::Error::DeviceDisconnected))
+            ) {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn replug_restores_session() {
+        let (sim, transport) = Simulator::new();
+        let replugged = Arc::new(AtomicBool::new(false));
+        let makcu = Makcu::from_transport_with_reconnect(
+            transport,
+            reject_while_disconnected(Duration::from_millis(10)),
+            {
+                let sim = sim.clone();
+                let replugged = Arc::clone(&replugged);
+                move || match replugged.load(Ordering::Acquire) {
+                    true => Ok(sim.plug()),
+                    false => Err(io::ErrorKind::NotFound.into()),
+                }
+            },
+        )
+        .unwrap();
+        makcu.lock(LockTarget::X).await.unwrap();
+        makcu.lock(LockTarget::Y).await.unwrap();
+        makcu.version().await.unwrap();
+
+        sim.unplug();
+        until_disconnected(&makcu).await;
+        // 보내지 못한 해제도 다시 연결된 장치에 잠금이 되살아나지 않게 한다
+        assert!(makcu.unlock(LockTarget::X).await.is_err());
+        replugged.store(true, Ordering::Release);
+
+        tokio::time::timeout(WAIT, async {
+            while makcu.version().await.is_err() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(!sim.is_locked(LockTarget::X));
+        assert!(sim.is_locked(LockTarget::Y));
+    }
+
+    #[tokio::test]
+    async fn close_interrupts_reconnect() {
+        let (sim, transport) = Simulator::new();
+        let makcu = Makcu::from_transport_with_reconnect(
+            transport,
+            reject_while_disconnected(Duration::from_secs(60)),
+            || Err::<SimTransport, _>(io::ErrorKind::NotFound.into()),
+        )
+        .unwrap();
+        let state = makcu.connection_state();
+
+        sim.unplug();
+        until_disconnected(&makcu).await;
+        tokio::time::timeout(WAIT, makcu.close())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            *state.borrow(),
+            ConnectionState::Disconnected(DisconnectReason::Closed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn lost_leaves_connected_state() {
+        let (sim, transport) = Simulator::new();
+        let makcu =
+            Makcu::from_transport_with_reconnect(transport, ReconnectPolicy::default(), || {
+                Err::<SimTransport, _>(io::ErrorKind::NotFound.into())
+            })
+            .unwrap();
+        let mut events = makcu.subscribe_connection_events();
+        let state = makcu.connection_state();
+
+        sim.unplug();
+        let event = tokio::time::timeout(WAIT, events.recv()).await.unwrap();
+        assert!(matches!(event, Ok(ConnectionEvent::Lost(_))));
+        assert!(matches!(
+            *state.borrow(),
+            ConnectionState::Reconnecting { attempt: 1 }
+        ));
+        makcu.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn coalesced_moves_stop_at_other_commands() {
+        let (sim, makcu) = connect();
+        makcu.set_coalesce_moves(true);
+        sim.stall();
+        for x in [1, 2, 3] {
+            makcu.mouse_move(x, 0).await.unwrap();
+        }
+        makcu.press(MouseButton::Left).await.unwrap();
+        for x in [4, 5] {
+            makcu.mouse_move(x, 0).await.unwrap();
+        }
+        sim.resume();
+        makcu.version().await.unwrap();
+
+        let commands = sim.commands();
+        let press = commands.iter().position(|c| c == "km.left(1)").unwrap();
+        // 워커가 첫 이동을 이미 꺼냈다면 그 이동은 따로 간다
+        assert!((1..=2).contains(&press));
+        let moved: i32 = commands[..press]
+            .iter()
+            .map(|c| {
+                let (x, y) = c
+                    .strip_prefix("km.move(")
+                    .and_then(|c| c.strip_suffix(')'))
+                    .and_then(|c| c.split_once(','))
+                    .unwrap();
+                assert_eq!(y, "0");
+                x.parse::<i32>().unwrap()
+            })
+            .sum();
+        assert_eq!(moved, 6);
+        assert_eq!(commands[press + 1..], ["km.move(9,0)", "km.version()"]);
+        assert_eq!(sim.position(), (15, 0));
+    }
+
+    #[tokio::test]
+    async fn stats_count_command_lines() {
+        let (_sim, makcu) = connect();
+        makcu.version().await.unwrap();
+        let before = makcu.stats();
+
+        // 범위를 넘는 이동은 세 줄로 나눠 보낸다
+        makcu.mouse_move(300, 0).await.unwrap();
+        makcu.version().await.unwrap();
+        let after = makcu.stats();
+
+        assert_eq!(after.commands_sent - before.commands_sent, 4);
+        assert_eq!(
+            after.response_latency.count() - before.response_latency.count(),
+            1
+        );
+        assert_eq!(after.queue_depth, 0);
+    }
+
+    #[tokio::test]
+    async fn release_overtakes_only_later_moves() {
+        let (sim, makcu) = connect();
+        sim.stall();
+        makcu.mouse_move(1, 0).await.unwrap();
+        makcu.mouse_move(2, 0).await.unwrap();
+        makcu.press(MouseButton::Left).await.unwrap();
+        makcu.mouse_move(3, 0).await.unwrap();
+        makcu.mouse_move(4, 0).await.unwrap();
+        makcu.release(MouseButton::Left).await.unwrap();
+        sim.resume();
+        makcu.version().await.unwrap();
+
+        assert_eq!(
+            sim.commands(),
+            [
+                "km.move(1,0)",
+                "km.move(2,0)",
+                "km.left(1)",
+                "km.left(0)",
+                "km.move(3,0)",
+                "km.move(4,0)",
+                "km.version()",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn release_does_not_wait_for_later_commands() {
+        let (sim, makcu) = connect();
+        sim.stall();
+        makcu.mouse_move(1, 0).await.unwrap();
+        makcu.mouse_move(2, 0).await.unwrap();
+        makcu.release(MouseButton::Left).await.unwrap();
+        makcu.press(MouseButton::Left).await.unwrap();
+        sim.resume();
+        makcu.version().await.unwrap();
+
+        // 워커가 첫 이동을 이미 꺼냈는지에 따라 그 이동의 자리만 달라진다
+        let mut commands = sim.commands();
+        commands.retain(|c| c != "km.move(1,0)");
+        assert_eq!(
+            commands,
+            ["km.left(0)", "km.move(2,0)", "km.left(1)", "km.version()"]
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+// 버킷 i 는 2^i µs 미만의 지연을 센다. 마지막 버킷은 그 이상을 모두 받는다
+const BUCKETS: usize = 24;
+
+/// 2의 거듭제곱 µs 경계로 나눈 지연 분포
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    sum: Duration,
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        let count = u32::try_from(self.count).ok().filter(|&count| count > 0)?;
+        Some(self.sum / count)
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// `q` (0.0 ~ 1.0) 분위수가 속한 버킷의 상한
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((self.count as f64) * q.clamp(0.0, 1.0)).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        self.buckets().find_map(|(upper, count)| {
+            seen += count;
+            (seen >= rank).then_some(upper.min(self.max))
+        })
+    }
+
+    /// 버킷 상한과 그 버킷에 든 표본 수
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| (bucket_upper(i), count))
+    }
+}
+
+fn bucket_upper(index: usize) -> Duration {
+    if index + 1 == BUCKETS {
+        Duration::MAX
+    } else {
+        Duration::from_micros(1 << index)
+    }
+}
+
+fn bucket_index(latency: Duration) -> usize {
+    let micros = latency.as_micros();
+    ((u128::BITS - micros.leading_zeros()) as usize).min(BUCKETS - 1)
+}
+
+#[derive(Default)]
+struct AtomicHistogram {
+    buckets: [AtomicU64; BUCKETS],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+}
+
+impl AtomicHistogram {
+    fn record(&self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[bucket_index(latency)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> LatencyHistogram {
+        LatencyHistogram {
+            buckets: std::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed)),
+            count: self.count.load(Ordering::Relaxed),
+            sum: Duration::from_nanos(self.sum_nanos.load(Ordering::Relaxed)),
+            max: Duration::from_nanos(self.max_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// 연결이 열린 뒤부터 누적된 시리얼 링크 통계
+#[derive(Debug, Clone)]
+pub struct Stats {
+    /// 장치에 쓴 명령 줄 수. 합쳐진 이동은 하나로, 나눠 보낸 큰 이동은 줄마다 센다
+    pub commands_sent: u64,
+    /// 다른 이동에 합쳐져 따로 보내지 않은 이동 수
+    pub moves_coalesced: u64,
+    pub bytes_written: u64,
+    pub bytes_read: u64,
+    /// 아직 워커가 꺼내지 않은 명령 수
+    pub queue_depth: usize,
+    /// 형식이 잘못됐거나 느린 구독자 때문에 덮어쓴 버튼 보고 수
+    pub dropped_button_updates: u64,
+    pub reconnects: u64,
+    /// 명령을 큐에 넣은 뒤 쓰기가 끝날 때까지
+    pub write_latency: LatencyHistogram,
+    /// 응답을 기다리는 명령을 큐에 넣은 뒤 응답이 올 때까지
+    pub response_latency: LatencyHistogram,
+}
+
+#[derive(Default)]
+pub(crate) struct Counters {
+    pub commands_sent: AtomicU64,
+    pub moves_coalesced: AtomicU64,
+    pub bytes_written: AtomicU64,
+    pub bytes_read: AtomicU64,
+    pub dropped_button_updates: AtomicU64,
+    pub reconnects: AtomicU64,
+    write_latency: AtomicHistogram,
+    response_latency: AtomicHistogram,
+}
+
+impl Counters {
+    pub fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn record_write(&self, latency: Duration) {
+        self.write_latency.record(latency);
+    }
+
+    pub fn record_response(&self, latency: Duration) {
+        self.response_latency.record(latency);
+    }
+
+    pub fn snapshot(&self, queue_depth: usize) -> Stats {
+        Stats {
+            commands_sent: self.commands_sent.load(Ordering::Relaxed),
+            moves_coalesced: self.moves_coalesced.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            queue_depth,
+            dropped_button_updates: self.dropped_button_updates.load(Ordering::Relaxed),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+            write_latency: self.write_latency.snapshot(),
+            response_latency: self.response_latency.snapshot(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_boundaries() {
+        assert_eq!(bucket_index(Duration::ZERO), 0);
+        assert_eq!(bucket_index(Duration::from_nanos(999)), 0);
+        assert_eq!(bucket_index(Duration::from_micros(1)), 1);
+        assert_eq!(bucket_index(Duration::from_micros(3)), 2);
+        assert_eq!(bucket_index(Duration::from_micros(4)), 3);
+        assert_eq!(bucket_index(Duration::from_secs(3600)), BUCKETS - 1);
+
+        // 표본은 자기 버킷의 상한보다 작다
+        for micros in [0, 1, 5, 1000, 1 << 20] {
+            let latency = Duration::from_micros(micros);
+            assert!(latency < bucket_upper(bucket_index(latency)));
+        }
+    }
+
+    #[test]
+    fn quantile_is_bucket_upper_bound() {
+        let histogram = AtomicHistogram::default();
+        assert_eq!(histogram.snapshot().quantile(0.5), None);
+
+        for _ in 0..9 {
+            histogram.record(Duration::from_micros(10));
+        }
+        histogram.record(Duration::from_millis(5));
+        let snapshot = histogram.snapshot();
+
+        assert_eq!(snapshot.count(), 10);
+        assert_eq!(snapshot.quantile(0.0), Some(Duration::from_micros(16)));
+        assert_eq!(snapshot.quantile(0.9), Some(Duration::from_micros(16)));
+        // 가장 느린 표본은 버킷 상한 대신 최댓값으로 자른다
+        assert_eq!(snapshot.quantile(1.0), Some(Duration::from_millis(5)));
+        assert_eq!(snapshot.max(), Duration::from_millis(5));
+        assert_eq!(
+            snapshot.mean(),
+            Some((Duration::from_micros(90) + Duration::from_millis(5)) / 10)
+        );
+    }
+}
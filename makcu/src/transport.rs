@@ -0,0 +1,225 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+use serialport::SerialPort;
+
+/// 시리얼 워커가 읽고 쓰는 바이트 스트림.
+///
+/// 읽기 스레드와 쓰기 스레드가 `try_clone` 으로 얻은 핸들을 하나씩 쓴다.
+/// 읽기는 `set_read_timeout` 으로 지정한 시간 안에 `TimedOut` 이나
+/// `WouldBlock` 으로 돌아와야 한다. 그래야 읽기 스레드가 종료 요청을 확인할 수 있다.
+pub trait Transport: Read + Write + Send + 'static {
+    fn name(&self) -> Option<String>;
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+
+    /// 같은 연결을 가리키는 핸들을 하나 더 만든다
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>>;
+
+    /// 보레이트 개념이 없는 트랜스포트는 무시한다
+    fn set_baud_rate(&mut self, _baud_rate: u32) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Box<dyn SerialPort> {
+    fn name(&self) -> Option<String> {
+        SerialPort::name(self.as_ref())
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.set_timeout(timeout)?;
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        let port = SerialPort::try_clone(self.as_ref())?;
+        Ok(Box::new(port))
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> io::Result<()> {
+        SerialPort::set_baud_rate(self.as_mut(), baud_rate)?;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl Transport for serialport::TTYPort {
+    fn name(&self) -> Option<String> {
+        SerialPort::name(self)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.set_timeout(timeout)?;
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.try_clone_native()?))
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> io::Result<()> {
+        SerialPort::set_baud_rate(self, baud_rate)?;
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl Transport for serialport::COMPort {
+    fn name(&self) -> Option<String> {
+        SerialPort::name(self)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.set_timeout(timeout)?;
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.try_clone_native()?))
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> io::Result<()> {
+        SerialPort::set_baud_rate(self, baud_rate)?;
+        Ok(())
+    }
+}
+
+impl Transport for TcpStream {
+    fn name(&self) -> Option<String> {
+        self.peer_addr().ok().map(|addr| addr.to_string())
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, Some(timeout))
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(TcpStream::try_clone(self)?))
+    }
+}
+
+#[derive(Default)]
+struct Channel {
+    buf: Mutex<ChannelState>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct ChannelState {
+    data: VecDeque<u8>,
+    closed: bool,
+}
+
+impl Channel {
+    fn close(&self) {
+        self.buf.lock().unwrap().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+// 마지막 핸들이 사라질 때 양쪽 채널을 닫는다
+struct Ends {
+    rx: Arc<Channel>,
+    tx: Arc<Channel>,
+}
+
+impl Drop for Ends {
+    fn drop(&mut self) {
+        self.rx.close();
+        self.tx.close();
+    }
+}
+
+/// 메모리 안에서 동작하는 양방향 파이프의 한쪽 끝
+pub struct Pipe {
+    ends: Arc<Ends>,
+    timeout: Option<Duration>,
+}
+
+/// 서로 연결된 파이프 한 쌍을 만든다
+pub fn pipe() -> (Pipe, Pipe) {
+    let a = Arc::new(Channel::default());
+    let b = Arc::new(Channel::default());
+
+    let left = Pipe {
+        ends: Arc::new(Ends {
+            rx: Arc::clone(&a),
+            tx: Arc::clone(&b),
+        }),
+        timeout: None,
+    };
+    let right = Pipe {
+        ends: Arc::new(Ends { rx: b, tx: a }),
+        timeout: None,
+    };
+    (left, right)
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let rx = &self.ends.rx;
+        let mut state = rx.buf.lock().unwrap();
+        while state.data.is_empty() {
+            if state.closed {
+                return Ok(0);
+            }
+            state = match self.timeout {
+                Some(timeout) => {
+                    let (state, result) = rx.ready.wait_timeout(state, timeout).unwrap();
+                    if result.timed_out() && state.data.is_empty() && !state.closed {
+                        return Err(io::ErrorKind::TimedOut.into());
+                    }
+                    state
+                }
+                None => rx.ready.wait(state).unwrap(),
+            };
+        }
+
+        let n = buf.len().min(state.data.len());
+        for (dst, src) in buf.iter_mut().zip(state.data.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let tx = &self.ends.tx;
+        let mut state = tx.buf.lock().unwrap();
+        if state.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        state.data.extend(buf);
+        tx.ready.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Pipe {
+    fn name(&self) -> Option<String> {
+        Some("pipe".to_owned())
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = Some(timeout);
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(Pipe {
+            ends: Arc::clone(&self.ends),
+            timeout: self.timeout,
+        }))
+    }
+}
@@ -0,0 +1,241 @@
+//! 마지막 값 하나만 보관하는 채널. 비동기 태스크와 일반 스레드 모두에서 기다릴 수 있다
+
+use std::{
+    ops::Deref,
+    sync::{
+        Arc, RwLock, RwLockReadGuard,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use event_listener::{Event, Listener};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("watch channel closed")]
+pub struct RecvError;
+
+struct State<T> {
+    value: T,
+    version: u64,
+    closed: bool,
+}
+
+struct Shared<T> {
+    state: RwLock<State<T>>,
+    changed: Event,
+    senders: AtomicUsize,
+}
+
+pub fn channel<T>(value: T) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: RwLock::new(State {
+            value,
+            version: 0,
+            closed: false,
+        }),
+        changed: Event::new(),
+        senders: AtomicUsize::new(1),
+    });
+    let rx = Receiver {
+        shared: Arc::clone(&shared),
+        version: 0,
+    };
+    (Sender { shared }, rx)
+}
+
+pub struct Ref<'a, T>(RwLockReadGuard<'a, State<T>>);
+
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0.value
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// 구독자가 없어도 값을 바꾸고 이전 값을 돌려준다
+    pub fn send_replace(&self, value: T) -> T {
+        let previous = {
+            let mut state = self.shared.state.write().unwrap();
+            state.version += 1;
+            std::mem::replace(&mut state.value, value)
+        };
+        self.shared.changed.notify(usize::MAX);
+        previous
+    }
+
+    pub fn borrow(&self) -> Ref<'_, T> {
+        Ref(self.shared.state.read().unwrap())
+    }
+
+    /// 지금 값은 이미 본 것으로 치는 새 구독자
+    pub fn subscribe(&self) -> Receiver<T> {
+        let version = self.shared.state.read().unwrap().version;
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            version,
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.state.write().unwrap().closed = true;
+            self.shared.changed.notify(usize::MAX);
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    version: u64,
+}
+
+impl<T> Receiver<T> {
+    pub fn borrow(&self) -> Ref<'_, T> {
+        Ref(self.shared.state.read().unwrap())
+    }
+
+    /// 값을 읽고 본 것으로 표시한다
+    pub fn borrow_and_update(&mut self) -> Ref<'_, T> {
+        let state = self.shared.state.read().unwrap();
+        self.version = state.version;
+        Ref(state)
+    }
+
+    pub fn has_changed(&self) -> Result<bool, RecvError> {
+        let state = self.shared.state.read().unwrap();
+        if state.version != self.version {
+            return Ok(true);
+        }
+        if state.closed {
+            return Err(RecvError);
+        }
+        Ok(false)
+    }
+
+    /// 아직 보지 않은 값이 생길 때까지 기다린다
+    pub async fn changed(&mut self) -> Result<(), RecvError> {
+        loop {
+            // 확인과 대기 사이에 온 알림을 놓치지 않도록 먼저 등록한다
+            let listener = self.shared.changed.listen();
+            if self.mark_changed()? {
+                return Ok(());
+            }
+            listener.await;
+        }
+    }
+
+    /// [`Receiver::changed`] 와 같지만 현재 스레드를 막고 기다린다
+    pub fn blocking_changed(&mut self) -> Result<(), RecvError> {
+        loop {
+            let listener = self.shared.changed.listen();
+            if self.mark_changed()? {
+                return Ok(());
+            }
+            listener.wait();
+        }
+    }
+
+    fn mark_changed(&mut self) -> Result<bool, RecvError> {
+        let changed = self.has_changed()?;
+        if changed {
+            self.version = self.shared.state.read().unwrap().version;
+        }
+        Ok(changed)
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+            version: self.version,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn has_changed_tracks_seen_version() {
+        let (tx, mut rx) = channel(0);
+        assert_eq!(rx.has_changed(), Ok(false));
+
+        tx.send_replace(1);
+        assert_eq!(rx.has_changed(), Ok(true));
+        assert_eq!(*rx.borrow(), 1);
+        // borrow 만으로는 본 것으로 치지 않는다
+        assert_eq!(rx.has_changed(), Ok(true));
+        assert_eq!(*rx.borrow_and_update(), 1);
+        assert_eq!(rx.has_changed(), Ok(false));
+
+        let late = tx.subscribe();
+        assert_eq!(late.has_changed(), Ok(false));
+    }
+
+    #[tokio::test]
+    async fn changed_wakes_on_send() {
+        let (tx, mut rx) = channel(0);
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            tx.send_replace(1);
+            tx
+        });
+
+        rx.changed().await.unwrap();
+        assert_eq!(*rx.borrow(), 1);
+        assert_eq!(rx.has_changed(), Ok(false));
+        drop(sender.join().unwrap());
+    }
+
+    #[test]
+    fn blocking_changed_wakes_on_send() {
+        let (tx, mut rx) = channel(0);
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            tx.send_replace(1);
+            tx
+        });
+
+        rx.blocking_changed().unwrap();
+        assert_eq!(*rx.borrow(), 1);
+        drop(sender.join().unwrap());
+    }
+
+    #[tokio::test]
+    async fn closed_after_last_sender_drops() {
+        let (tx, mut rx) = channel(0);
+        let clone = tx.clone();
+        drop(tx);
+        assert_eq!(rx.has_changed(), Ok(false));
+
+        // 닫히기 전에 보낸 값은 먼저 받는다
+        clone.send_replace(1);
+        drop(clone);
+        assert_eq!(rx.changed().await, Ok(()));
+        assert_eq!(*rx.borrow(), 1);
+        assert_eq!(rx.changed().await, Err(RecvError));
+        assert_eq!(rx.blocking_changed(), Err(RecvError));
+        assert_eq!(rx.has_changed(), Err(RecvError));
+    }
+}